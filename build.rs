@@ -10,12 +10,24 @@ use std::thread;
 /// Build from https://wasm-stat.us that we are known to work with.
 const WASM_BUILD: &'static str = "9901";
 
+/// The Binaryen C APIs bound in src/binaryen/sys.rs which the first Binaryen revisions we used
+/// don't have. The binaryen submodule must be recent enough to declare them.
+const BINARYEN_APIS: &'static [&'static str] = &["BinaryenAddGlobal",
+                                                 "BinaryenGetGlobal",
+                                                 "BinaryenModuleRunPasses",
+                                                 "BinaryenSetGlobal",
+                                                 "BinaryenSetOptimizeLevel",
+                                                 "BinaryenSetShrinkLevel"];
+
 fn main() {
+    if !Path::new("binaryen/.git").exists() {
+        Command::new("git").args(&["submodule", "update", "--init"])
+            .status().expect("error updating submodules");
+    }
+    check_binaryen_apis();
+    write_binaryen_passes();
+
     let cmake = thread::spawn(|| {
-        if !Path::new("binaryen/.git").exists() {
-            Command::new("git").args(&["submodule", "update", "--init"])
-                .status().expect("error updating submodules");
-        }
         cmake::Config::new("binaryen")
             .define("BUILD_STATIC_LIB", "ON")
             .build()
@@ -40,6 +52,42 @@ fn main() {
     print_deps(Path::new("binaryen"));
 }
 
+/// Fails the build when the binaryen submodule is too old for the bindings.
+fn check_binaryen_apis() {
+    let mut header = String::new();
+    File::open("binaryen/src/binaryen-c.h")
+        .and_then(|mut file| file.read_to_string(&mut header))
+        .expect("error reading the binaryen C API");
+
+    let missing: Vec<_> = BINARYEN_APIS.iter()
+        .filter(|api| !header.contains(&format!("{}(", api)))
+        .collect();
+    if !missing.is_empty() {
+        panic!("the binaryen submodule doesn't have the C APIs {:?}, update it to a more recent \
+                revision",
+               missing);
+    }
+}
+
+/// Writes the names of the passes Binaryen registers, as an array included by
+/// src/binaryen/builder.rs: Binaryen aborts when asked to run an unknown pass.
+fn write_binaryen_passes() {
+    let mut registry = String::new();
+    File::open("binaryen/src/passes/pass.cpp")
+        .and_then(|mut file| file.read_to_string(&mut registry))
+        .expect("error reading the binaryen pass registry");
+
+    let names: Vec<_> = registry.split("registerPass(\"")
+        .skip(1)
+        .filter_map(|call| call.split('"').next())
+        .map(|name| format!("{:?}", name))
+        .collect();
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("binaryen_passes.rs");
+    File::create(path)
+        .and_then(|mut file| writeln!(file, "&[{}]", names.join(", ")))
+        .expect("error writing the binaryen pass names");
+}
+
 fn print_deps(path: &Path) {
     for e in path.read_dir().unwrap().filter_map(|e| e.ok()) {
         let file_type = e.file_type().unwrap();
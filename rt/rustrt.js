@@ -3,7 +3,20 @@
 // This currently assumes it's running under d8, the V8 shell. We'll
 // probably want to make it engine-independent.
 
-let buffer = readbuffer(arguments[0]);
+// The module to run: the first argument, unless a loader emitted by `--emit=js` sets its path
+let buffer = readbuffer(typeof wasm_path === "undefined" ? arguments[0] : wasm_path);
+
+let instance;
+
+// Decodes the UTF-8 string at `ptr` in the module's memory
+let read_str = function(ptr, len) {
+    let bytes = new Uint8Array(instance.exports.memory, ptr, len);
+    let encoded = "";
+    for(let i = 0; i < bytes.length; i++) {
+        encoded += "%" + ("0" + bytes[i].toString(16)).slice(-2);
+    }
+    return decodeURIComponent(encoded);
+}
 
 let empty_function = function() {}
 let module_handler = {
@@ -16,6 +29,28 @@ let module_handler = {
                 }
             };
         }
+        if(module_name == "env") {
+            return new Proxy({
+                rust_panic: function(msg_ptr, msg_len, file_ptr, file_len, line) {
+                    print("panicked at '" + read_str(msg_ptr, msg_len) + "', " +
+                          read_str(file_ptr, file_len) + ":" + line);
+                },
+                __rust_stack_overflow: function() {
+                    print("stack overflow");
+                },
+                __unsupported: function(fn_id) {
+                    print("called fn " + fn_id + ", which uses unsupported MIR");
+                }
+            }, {
+                get: function(target, func_name) {
+                    if(func_name in target) {
+                        return target[func_name];
+                    }
+                    print("Rust requested runtime function env::" + func_name);
+                    return empty_function;
+                }
+            });
+        }
         return new Proxy({}, {
             get: function(target, func_name) {
                 print("Rust requested runtime function " + module_name + "::" + func_name);
@@ -26,6 +61,6 @@ let module_handler = {
 };
 let proxy_ffi = new Proxy({}, module_handler);
 
-let foo = Wasm.instantiateModule(buffer, proxy_ffi);
+instance = Wasm.instantiateModule(buffer, proxy_ffi);
 
-foo.exports.rust_entry();
+instance.exports.rust_entry();
@@ -40,7 +40,9 @@ mod wasm {
         unsafe { _print_i32(i); }
     }
 
+    #[link(name = "spectest")]
     extern {
+        #[link_name = "print"]
         fn _print_i32(i: isize);
     }
 }
@@ -12,10 +12,13 @@ extern crate rustc_driver;
 #[link_args = "-lstdc++ -static-libstdc++"]
 extern "C" {}
 
-use getopts::{getopts, optflag, optopt};
-use mir2wasm::trans::{self, WasmTransOptions};
+use getopts::{getopts, optflag, optflagopt, optopt};
+use mir2wasm::error::ErrorKind;
+use mir2wasm::trans::{self, EmitKind, Invocation, OptLevel, PanicStrategy, ReportFormat,
+                      StackCheck, StackPlacement, WasmTransOptions};
 use rustc::session::Session;
 use rustc_driver::{driver, CompilerCalls};
+use std::io::{self, Write};
 use std::process;
 
 struct WasmCompilerCalls {
@@ -47,39 +50,125 @@ impl<'a> CompilerCalls<'a> for WasmCompilerCalls {
             } else {
                 None
             };
-            trans::trans_crate(&state.tcx.unwrap(),
-                               state.mir_map.unwrap(),
-                               entry_fn,
-                               &options)
-                .expect("error translating crate");
+            let result = trans::trans_crate(&state.tcx.unwrap(),
+                                            state.mir_map.unwrap(),
+                                            &state.analysis.unwrap().access_levels,
+                                            entry_fn,
+                                            &options);
+            if let Err(error) = result {
+                if let ErrorKind::InvalidModule = *error.kind() {
+                    state.session.note_without_error("the validator's errors are above, rerun \
+                                                      with --validate-each-fn to find the fn \
+                                                      and MIR generating invalid wasm");
+                }
+                state.session.fatal(&error.to_string());
+            }
         });
 
         control
     }
 }
 
+/// Reports an invalid command line argument, and exits.
+fn option_error(message: &str) -> ! {
+    writeln!(io::stderr(), "error: {}", message).expect("could not write to stderr");
+    process::exit(1);
+}
+
+fn parse_number(option: &str, value: &str) -> u32 {
+    match value.parse() {
+        Ok(number) => number,
+        Err(_) => option_error(&format!("invalid --{} '{}', expected a number", option, value)),
+    }
+}
+
 fn main() {
     env_logger::init().unwrap();
 
     let opts = &[optflag("r",
                          "run",
                          "run the compiled module through the interpreter, without printing it"),
+                 optopt("",
+                        "invoke",
+                        "interpret the module calling the exported fn NAME with the numeric \
+                         ARGS following it, and print its result",
+                        "NAME ARGS..."),
                  optopt("o", "", "write a binary wasm module to FILE", "FILE"),
-                 optflag("O", "", "optimize the compiled wast module"),
+                 optopt("",
+                        "emit",
+                        "the artifacts to write next to the -o FILE, or named after the crate: \
+                         the binary or text module, a JS loader running it, and the MIR",
+                        "wasm,wast,js,mir"),
+                 optflagopt("O",
+                            "",
+                            "optimize the compiled module, removing the unused fns and imports \
+                             (2 without a level)",
+                            "0|1|2|3|s|z"),
+                 optopt("",
+                        "passes",
+                        "run these binaryen passes instead of the default pipeline of the \
+                         optimization level",
+                        "PASS,PASS,..."),
                  optflag("q", "", "do not print the compiled wast module"),
+                 optopt("",
+                        "panic",
+                        "how panics are translated: trap at the panic (the default), or unwind \
+                         running the cleanups",
+                        "abort|unwind"),
+                 optopt("", "initial-memory", "initial size of the linear memory", "PAGES"),
+                 optopt("",
+                        "max-memory",
+                        "maximum size the linear memory can grow to",
+                        "PAGES"),
+                 optopt("", "stack-size", "size of the stack in linear memory", "BYTES"),
+                 optopt("",
+                        "stack-placement",
+                        "place the stack after the statics (the default), or at the end of the \
+                         initial memory",
+                        "after-statics|end-of-memory"),
+                 optopt("",
+                        "stack-check",
+                        "check for stack overflows in fn prologues, trapping or calling \
+                         env.__rust_stack_overflow first (trap in debug builds, off otherwise)",
+                        "off|trap|handler"),
+                 optflag("",
+                         "allow-unsupported",
+                         "replace the fns using unsupported MIR with stubs calling \
+                          env.__unsupported(fn_id) and trapping, with a warning instead of an \
+                          error"),
+                 optopt("",
+                        "report-unsupported",
+                        "only classify the MIR of every fn, generic or not, to print a report \
+                         of the unsupported constructs, with their counts and locations",
+                        "json"),
+                 optflagopt("",
+                            "trace-binaryen",
+                            "trace the binaryen API calls into a C program building the same \
+                             module, to reproduce binaryen bugs (next to the -o FILE by default)",
+                            "FILE"),
+                 optflag("",
+                         "validate-each-fn",
+                         "report the fns the validator's errors are in, with the MIR \
+                          generating the invalid wasm"),
                  optflag("h", "help", "display this help message")];
 
     let mut rustc_args = Vec::new();
     let mut wasm_args = Vec::new();
+    let mut invoke_args = Vec::new();
 
     fn find_wasm_arg<'a>(s: &String,
                          opts: &'a [getopts::OptGroup])
                          -> Option<&'a getopts::OptGroup> {
+        // long options can also be given as `--name=value`
+        let name = s.splitn(2, '=').next().expect("");
         for o in opts {
-            if s.starts_with("--") && &s[2..] == &o.long_name {
+            if name.starts_with("--") && &name[2..] == &o.long_name {
                 return Some(o);
             }
-            if s.starts_with("-") && &s[1..] == &o.short_name {
+            // short options can have their value attached, like `-O2`
+            if !o.short_name.is_empty() && s.starts_with("-") && !s.starts_with("--") &&
+               s[1..].starts_with(&o.short_name) &&
+               (s.len() == 2 || o.hasarg != getopts::HasArg::No) {
                 return Some(o);
             }
         }
@@ -95,18 +184,33 @@ fn main() {
             Some(arg) => {
                 match find_wasm_arg(&arg, opts) {
                     Some(opt) => {
+                        // `--name=value` and `-nvalue`
+                        let has_attached_value = arg.contains('=') ||
+                                                 (!arg.starts_with("--") && arg.len() > 2);
                         wasm_args.push(arg);
 
                         match opt.hasarg {
-                            getopts::HasArg::Yes => {
-                                wasm_args.push(argv.next().expect("missing required argument"))
-                            }
-                            getopts::HasArg::No => (),
-                            getopts::HasArg::Maybe => {
-                                if argv.peek().map_or(false, |s| s.starts_with("-")) {
-                                    wasm_args.push(argv.next().expect("this was just here"));
+                            getopts::HasArg::Yes if !has_attached_value => {
+                                match argv.next() {
+                                    Some(value) => wasm_args.push(value),
+                                    None => {
+                                        option_error(&format!("missing value for option '{}'",
+                                                              opt.long_name))
+                                    }
                                 }
                             }
+                            // optional values have to be attached, as the next argument may be
+                            // the crate
+                            getopts::HasArg::Yes |
+                            getopts::HasArg::No |
+                            getopts::HasArg::Maybe => {}
+                        }
+
+                        // the arguments of the invoked fn are the numbers after its name
+                        if opt.long_name == "invoke" {
+                            while argv.peek().map_or(false, |s| s.parse::<f64>().is_ok()) {
+                                invoke_args.push(argv.next().expect("this was just here"));
+                            }
                         }
                     }
                     None => rustc_args.push(arg),
@@ -116,11 +220,15 @@ fn main() {
         }
     }
     info!("wasm args: {:?}", wasm_args);
+    info!("invoke args: {:?}", invoke_args);
     info!("rustc args: {:?}", rustc_args);
 
     let mut options = WasmTransOptions::new();
 
-    let matches = getopts(&wasm_args[..], opts).expect("could not parse command line arguments");
+    let matches = match getopts(&wasm_args[..], opts) {
+        Ok(matches) => matches,
+        Err(error) => option_error(&error.to_string()),
+    };
 
     if matches.opt_present("h") {
         let brief = format!("Usage: {} [options]", args[0]);
@@ -130,15 +238,118 @@ fn main() {
     if matches.opt_present("r") {
         options.interpret = true;
     }
+    if let Some(name) = matches.opt_str("invoke") {
+        options.invoke = Some(Invocation {
+            name: name,
+            args: invoke_args,
+        });
+    }
     if matches.opt_present("o") {
         options.binary_output_path = matches.opt_str("o");
     }
+    if let Some(kinds) = matches.opt_str("emit") {
+        for kind in kinds.split(',') {
+            options.emit.push(match kind {
+                "wasm" => EmitKind::Wasm,
+                "wast" => EmitKind::Wast,
+                "js" => EmitKind::Js,
+                "mir" => EmitKind::Mir,
+                _ => {
+                    option_error(&format!("unknown emit kind '{}', expected wasm, wast, js or mir",
+                                          kind))
+                }
+            });
+        }
+    }
     if matches.opt_present("O") {
-        options.optimize = true;
+        options.opt_level = match matches.opt_str("O") {
+            None => OptLevel::O2,
+            Some(level) => {
+                match level.as_ref() {
+                    "0" => OptLevel::O0,
+                    "1" => OptLevel::O1,
+                    "2" => OptLevel::O2,
+                    "3" => OptLevel::O3,
+                    "s" => OptLevel::Os,
+                    "z" => OptLevel::Oz,
+                    _ => {
+                        option_error(&format!("unknown optimization level '{}', expected 0, 1, \
+                                               2, 3, s or z",
+                                              level))
+                    }
+                }
+            }
+        };
+    }
+    if matches.opt_present("trace-binaryen") {
+        options.trace = true;
+        options.trace_path = matches.opt_str("trace-binaryen");
+    }
+    if matches.opt_present("validate-each-fn") {
+        options.validate_each_fn = true;
+    }
+    if let Some(passes) = matches.opt_str("passes") {
+        options.passes = passes.split(',').map(|pass| pass.to_owned()).collect();
     }
     if matches.opt_present("q") {
         options.print = false;
     }
+    if let Some(strategy) = matches.opt_str("panic") {
+        options.panic_strategy = match strategy.as_ref() {
+            "abort" => PanicStrategy::Abort,
+            "unwind" => PanicStrategy::Unwind,
+            _ => {
+                option_error(&format!("unknown panic strategy '{}', expected abort or unwind",
+                                      strategy))
+            }
+        };
+    }
+
+    if let Some(pages) = matches.opt_str("initial-memory") {
+        options.initial_memory_pages = parse_number("initial-memory", &pages);
+    }
+    if let Some(pages) = matches.opt_str("max-memory") {
+        options.max_memory_pages = Some(parse_number("max-memory", &pages));
+    }
+    if let Some(size) = matches.opt_str("stack-size") {
+        options.stack_size = parse_number("stack-size", &size);
+    }
+    if let Some(placement) = matches.opt_str("stack-placement") {
+        options.stack_placement = match placement.as_ref() {
+            "after-statics" => StackPlacement::AfterStatics,
+            "end-of-memory" => StackPlacement::EndOfMemory,
+            _ => {
+                option_error(&format!("unknown stack placement '{}', expected after-statics or \
+                                       end-of-memory",
+                                      placement))
+            }
+        };
+    }
+    if let Some(check) = matches.opt_str("stack-check") {
+        options.stack_check = Some(match check.as_ref() {
+            "off" => StackCheck::Off,
+            "trap" => StackCheck::Trap,
+            "handler" => StackCheck::Handler,
+            _ => {
+                option_error(&format!("unknown stack check '{}', expected off, trap or handler",
+                                      check))
+            }
+        });
+    }
+
+    if matches.opt_present("allow-unsupported") {
+        options.allow_unsupported = true;
+    }
+
+    if let Some(format) = matches.opt_str("report-unsupported") {
+        options.report_unsupported = Some(match format.as_ref() {
+            "json" => ReportFormat::Json,
+            _ => {
+                option_error(&format!("unknown unsupported report format '{}', expected json",
+                                      format))
+            }
+        });
+    }
 
     let mut compiler_calls = WasmCompilerCalls::new(options);
     match rustc_driver::run_compiler(&rustc_args, &mut compiler_calls) {
@@ -1,6 +1,19 @@
 use super::sys;
 
+use libc;
+use std::env;
 use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, Read};
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+/// The names of the passes Binaryen registers, found by the build script
+pub const PASS_NAMES: &'static [&'static str] = include!(concat!(env!("OUT_DIR"),
+                                                                 "/binaryen_passes.rs"));
 
 pub struct Module {
     // TODO: make this private
@@ -23,10 +36,47 @@ impl Module {
         unsafe { sys::BinaryenModuleValidate(self.module) == 1 }
     }
 
+    /// Validates the module, returning the validator's errors when it's invalid. Binaryen only
+    /// prints them to stderr, which is redirected to a temporary file meanwhile. When it can't
+    /// be, they are printed as usual instead.
+    pub fn validate(&mut self) -> io::Result<Option<String>> {
+        let path = env::temp_dir()
+            .join(format!("mir2wasm-validation-{}.txt", unsafe { libc::getpid() }));
+        let valid = {
+            let file = try!(File::create(&path));
+            let _redirect = Redirect::new(libc::STDERR_FILENO, &file).ok();
+            self.is_valid()
+        };
+
+        let mut errors = String::new();
+        try!(File::open(&path).and_then(|mut file| file.read_to_string(&mut errors)));
+        try!(fs::remove_file(&path));
+        Ok(if valid { None } else { Some(errors) })
+    }
+
     pub fn optimize(&mut self) {
         unsafe { sys::BinaryenModuleOptimize(self.module) }
     }
 
+    /// Runs the passes, which must be in `PASS_NAMES`.
+    pub fn run_passes<S: AsRef<str>>(&mut self, passes: &[S]) {
+        let passes: Vec<_> =
+            passes.iter().map(|pass| CString::new(pass.as_ref()).expect("")).collect();
+        let pass_ptrs: Vec<_> = passes.iter().map(|pass| pass.as_ptr()).collect();
+        unsafe {
+            sys::BinaryenModuleRunPasses(self.module,
+                                         pass_ptrs.as_ptr(),
+                                         pass_ptrs.len().into())
+        }
+    }
+
+    /// Prints the module in the text format to `file`.
+    pub fn print_to_file(&self, file: &File) -> io::Result<()> {
+        let _redirect = try!(Redirect::new(libc::STDOUT_FILENO, file));
+        unsafe { sys::BinaryenModulePrint(self.module) }
+        Ok(())
+    }
+
     pub fn create_func(&mut self) -> Fn {
         Fn {
             module: self,
@@ -56,6 +106,99 @@ impl Module {
             type_ref: ty,
         }
     }
+
+    pub fn create_global(&mut self,
+                         name: &str,
+                         ty: ReprType,
+                         mutable: bool,
+                         init: sys::BinaryenExpressionRef)
+                         -> Global {
+        let name = CString::new(name).expect("");
+        unsafe {
+            sys::BinaryenAddGlobal(self.module, name.as_ptr(), ty.into(), mutable as i8, init);
+        }
+        Global {
+            name: name,
+            ty: ty,
+        }
+    }
+}
+
+/// Redirects stdout or stderr to a file until dropped, for the Binaryen APIs only printing their
+/// output. Only supported on unix.
+struct Redirect {
+    fd: libc::c_int,
+    /// A duplicate of the redirected file descriptor, restored when dropped
+    saved: libc::c_int,
+}
+
+impl Redirect {
+    #[cfg(unix)]
+    fn new(fd: libc::c_int, file: &File) -> io::Result<Redirect> {
+        try!(io::stdout().flush());
+        try!(io::stderr().flush());
+        unsafe {
+            libc::fflush(ptr::null_mut());
+            let saved = libc::dup(fd);
+            if saved < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::dup2(file.as_raw_fd(), fd) < 0 {
+                let error = io::Error::last_os_error();
+                libc::close(saved);
+                return Err(error);
+            }
+            Ok(Redirect {
+                fd: fd,
+                saved: saved,
+            })
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn new(_fd: libc::c_int, _file: &File) -> io::Result<Redirect> {
+        Err(io::Error::new(io::ErrorKind::Other, "outputs can only be redirected on unix"))
+    }
+}
+
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        unsafe {
+            libc::fflush(ptr::null_mut());
+            libc::dup2(self.saved, self.fd);
+            libc::close(self.saved);
+        }
+    }
+}
+
+/// Traces the Binaryen API calls into a file, as a C program building the same module, until
+/// dropped. Binaryen prints the trace to stdout, so everything printed meanwhile ends up in the
+/// file.
+pub struct ApiTrace {
+    _redirect: Redirect,
+}
+
+impl ApiTrace {
+    pub fn new(file: &File) -> io::Result<ApiTrace> {
+        let redirect = try!(Redirect::new(libc::STDOUT_FILENO, file));
+        unsafe { sys::BinaryenSetAPITracing(true) }
+        Ok(ApiTrace { _redirect: redirect })
+    }
+}
+
+impl Drop for ApiTrace {
+    fn drop(&mut self) {
+        // ends the C program, before stdout is restored
+        unsafe { sys::BinaryenSetAPITracing(false) }
+    }
+}
+
+/// Sets the optimize and shrink levels of the following optimizations, global to Binaryen.
+pub fn set_optimize_levels(optimize_level: i32, shrink_level: i32) {
+    unsafe {
+        sys::BinaryenSetOptimizeLevel(optimize_level);
+        sys::BinaryenSetShrinkLevel(shrink_level);
+    }
 }
 
 impl Drop for Module {
@@ -206,6 +349,31 @@ impl<'module> Fn<'module> {
     }
 }
 
+pub struct Global {
+    name: CString,
+    ty: ReprType,
+}
+
+impl Global {
+    pub fn get(&self, module: &Module) -> sys::BinaryenExpressionRef {
+        unsafe { sys::BinaryenGetGlobal(module.module, self.name.as_ptr(), self.ty.into()) }
+    }
+
+    pub fn set(&self,
+               module: &Module,
+               value: sys::BinaryenExpressionRef)
+               -> sys::BinaryenExpressionRef {
+        unsafe { sys::BinaryenSetGlobal(module.module, self.name.as_ptr(), value) }
+    }
+
+    pub fn name(&self) -> &CString {
+        &self.name
+    }
+    pub fn ty(&self) -> ReprType {
+        self.ty
+    }
+}
+
 pub struct Var {
     // TODO: this would be nice to have, but it causes issues.
     // func: &'func Fn<'func>,
@@ -30,6 +30,9 @@ pub struct BinaryenImportRef(pub *mut c_void);
 #[repr(C)]
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct BinaryenExportRef(pub *mut c_void);
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BinaryenGlobalRef(pub *mut c_void);
 
 #[repr(C)]
 pub struct BinaryenLiteral {
@@ -278,6 +281,14 @@ extern "C" {
                             index: BinaryenIndex,
                             value: BinaryenExpressionRef)
                             -> BinaryenExpressionRef;
+    pub fn BinaryenGetGlobal(module: BinaryenModuleRef,
+                             name: *const c_char,
+                             type_: BinaryenType)
+                             -> BinaryenExpressionRef;
+    pub fn BinaryenSetGlobal(module: BinaryenModuleRef,
+                             name: *const c_char,
+                             value: BinaryenExpressionRef)
+                             -> BinaryenExpressionRef;
     pub fn BinaryenLoad(module: BinaryenModuleRef,
                         bytes: u32,
                         signed_: u8,
@@ -353,6 +364,15 @@ extern "C" {
                              externalName: *const c_char)
                              -> BinaryenExportRef;
 
+    // Globals
+
+    pub fn BinaryenAddGlobal(module: BinaryenModuleRef,
+                             name: *const c_char,
+                             type_: BinaryenType,
+                             mutable_: i8,
+                             init: BinaryenExpressionRef)
+                             -> BinaryenGlobalRef;
+
     // Function table. One per module
 
     pub fn BinaryenSetFunctionTable(module: BinaryenModuleRef,
@@ -382,6 +402,10 @@ extern "C" {
 
     pub fn BinaryenModuleOptimize(module: BinaryenModuleRef);
 
+    pub fn BinaryenModuleRunPasses(module: BinaryenModuleRef,
+                                   passes: *const *const c_char,
+                                   numPasses: BinaryenIndex);
+
     pub fn BinaryenModuleWrite(module: BinaryenModuleRef,
                                output: *mut c_char,
                                outputSize: size_t)
@@ -426,4 +450,8 @@ extern "C" {
     // Other APIs
 
     pub fn BinaryenSetAPITracing(on: bool);
+
+    pub fn BinaryenSetOptimizeLevel(level: i32);
+
+    pub fn BinaryenSetShrinkLevel(level: i32);
 }
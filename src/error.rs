@@ -10,5 +10,40 @@ error_chain! {
         "I/O error";
     }
 
-    errors { }
+    errors {
+        UnsupportedMir(count: usize) {
+            description("unsupported MIR")
+            display("could not translate the crate, due to {} unsupported MIR constructs", count)
+        }
+
+        Layout(ty: String, error: String) {
+            description("type layout failure")
+            display("could not compute the layout of `{}`: {}", ty, error)
+        }
+
+        TraitSelection(trait_ref: String, error: String) {
+            description("trait selection failure")
+            display("could not select an impl for `{}`: {}", trait_ref, error)
+        }
+
+        UnknownPass(name: String) {
+            description("unknown binaryen pass")
+            display("unknown binaryen pass `{}`", name)
+        }
+
+        InvalidModule {
+            description("invalid wasm module")
+            display("the generated wasm module is invalid")
+        }
+
+        InvalidFn(path: String) {
+            description("invalid wasm fn")
+            display("the wasm generated for `{}` is invalid", path)
+        }
+
+        SerializationOverflow(capacity: usize) {
+            description("serialization overflow")
+            display("the wasm module doesn't fit in the {} bytes serialization buffer", capacity)
+        }
+    }
 }
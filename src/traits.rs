@@ -1,3 +1,4 @@
+use error::*;
 use rustc::ty::{self, TyCtxt};
 use rustc::hir::def_id::DefId;
 
@@ -14,11 +15,11 @@ use syntax::codemap::DUMMY_SP;
 pub fn resolve_trait_method<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
                                       def_id: DefId,
                                       substs: &'tcx Substs<'tcx>)
-                                      -> (DefId, &'tcx Substs<'tcx>) {
+                                      -> Result<(DefId, &'tcx Substs<'tcx>)> {
     let method_item = tcx.impl_or_trait_item(def_id);
     let trait_id = method_item.container().id();
     let trait_ref = ty::Binder(ty::TraitRef::from_method(*tcx, trait_id, substs));
-    match fulfill_obligation(tcx, trait_ref) {
+    match try!(fulfill_obligation(tcx, trait_ref)) {
         traits::VtableImpl(vtable_impl) => {
             let impl_did = vtable_impl.impl_def_id;
             let mname = tcx.item_name(def_id);
@@ -27,42 +28,35 @@ pub fn resolve_trait_method<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
             let substs = substs.rebase_onto(*tcx, trait_id, vtable_impl.substs);
             let mth = get_impl_method(*tcx, impl_did, substs, mname);
 
-            (mth.method.def_id, mth.substs)
+            Ok((mth.method.def_id, mth.substs))
         }
 
         traits::VtableClosure(vtable_closure) => {
-            (vtable_closure.closure_def_id, vtable_closure.substs.func_substs)
+            Ok((vtable_closure.closure_def_id, vtable_closure.substs.func_substs))
         }
 
-        traits::VtableFnPointer(_fn_ty) => {
-            let _trait_closure_kind = tcx.lang_items.fn_trait_kind(trait_id).unwrap();
-            unimplemented!()
-            // let llfn = trans_fn_pointer_shim(ccx, trait_closure_kind, fn_ty);
-
-            // let method_ty = def_ty(tcx, def_id, substs);
-            // let fn_ptr_ty = match method_ty.sty {
-            //     ty::TyFnDef(_, _, fty) => tcx.mk_ty(ty::TyFnPtr(fty)),
-            //     _ => unreachable!("expected fn item type, found {}",
-            //                       method_ty)
-            // };
-            // Callee::ptr(immediate_rvalue(llfn, fn_ptr_ty))
+        // TODO: fn pointer shims and virtual calls
+        traits::VtableFnPointer(_) => {
+            let error = "calls through fn pointer shims are not supported";
+            Err(ErrorKind::TraitSelection(trait_ref.to_string(), error.to_string()).into())
         }
 
-        traits::VtableObject(ref _data) => {
-            unimplemented!()
-            // Callee {
-            //     data: Virtual(traits::get_vtable_index_of_object_method(
-            //                   tcx, data, def_id)),
-            //                   ty: def_ty(tcx, def_id, substs)
-            // }
+        traits::VtableObject(_) => {
+            let error = "calls to trait object methods are not supported";
+            Err(ErrorKind::TraitSelection(trait_ref.to_string(), error.to_string()).into())
+        }
+
+        vtable => {
+            Err(ErrorKind::TraitSelection(trait_ref.to_string(),
+                                          format!("unexpected vtable {:?}", vtable))
+                .into())
         }
-        vtable => unreachable!("resolved vtable bad vtable {:?} in trans", vtable),
     }
 }
 
 fn fulfill_obligation<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
                                 trait_ref: ty::PolyTraitRef<'tcx>)
-                                -> traits::Vtable<'tcx, ()> {
+                                -> Result<traits::Vtable<'tcx, ()>> {
     // Do the initial selection for the obligation. This yields the shallow result we are
     // looking for -- that is, what specific impl.
     tcx.infer_ctxt(None, None, Reveal::All).enter(|infcx| {
@@ -85,15 +79,15 @@ fn fulfill_obligation<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
                 debug!("Encountered ambiguity selecting `{:?}` during trans, \
                         presuming due to overflow",
                        trait_ref);
-                // NOTE: in trans, this is a tcx.sess.span_fatal(&self.span,...) error rather than
-                // a panic
-                panic!("reached the recursion limit during monomorphization \
-                        (selection ambiguity)");
+                // NOTE: in trans, this is a tcx.sess.span_fatal(&self.span,...) error
+                let error = "reached the recursion limit during monomorphization (selection \
+                             ambiguity)";
+                return Err(ErrorKind::TraitSelection(trait_ref.to_string(), error.to_string())
+                    .into());
             }
             Err(e) => {
-                panic!("Encountered error `{:?}` selecting `{:?}` during trans",
-                       e,
-                       trait_ref)
+                return Err(ErrorKind::TraitSelection(trait_ref.to_string(), format!("{:?}", e))
+                    .into());
             }
         };
 
@@ -103,7 +97,22 @@ fn fulfill_obligation<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
         let vtable = selection.map(|predicate| {
             fulfill_cx.register_predicate_obligation(&infcx, predicate);
         });
-        infcx.drain_fulfillment_cx_or_panic(DUMMY_SP, &mut fulfill_cx, &vtable)
+        // NOTE: in trans, these are span_bug!s, from `drain_fulfillment_cx_or_panic`
+        let vtable = match infcx.drain_fulfillment_cx(&mut fulfill_cx, &vtable) {
+            Ok(vtable) => vtable,
+            Err(errors) => {
+                return Err(ErrorKind::TraitSelection(trait_ref.to_string(),
+                                                     format!("{:?}", errors))
+                    .into());
+            }
+        };
+        match tcx.lift(&vtable) {
+            Some(vtable) => Ok(vtable),
+            None => {
+                let error = format!("uninferred types or regions in `{:?}`", vtable);
+                Err(ErrorKind::TraitSelection(trait_ref.to_string(), error).into())
+            }
+        }
     })
 }
 
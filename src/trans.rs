@@ -2,23 +2,32 @@ use libc::c_char;
 use error::*;
 use rustc::mir::repr::*;
 use rustc::mir::mir_map::MirMap;
+use rustc::mir::visit::{Visitor as MirVisitor, LvalueContext};
 use rustc::middle::const_val::ConstVal;
-use rustc_const_math::{ConstInt, ConstIsize};
+use rustc::middle::privacy::AccessLevels;
+use rustc_const_math::{ConstInt, ConstIsize, ConstUsize, ConstFloat};
 use rustc::ty::{self, TyCtxt, Ty, FnSig};
 use rustc::ty::layout::{self, Layout, Size};
 use rustc::ty::subst::Substs;
+use rustc::ty::fold::TypeFoldable;
 use rustc::hir::intravisit::{self, Visitor, FnKind};
 use rustc::hir::{FnDecl, Block};
 use rustc::hir::def_id::DefId;
+use rustc::hir::map as hir_map;
 use rustc::traits::Reveal;
+use rustc::session::config;
+use rustc_mir::pretty;
+use syntax::abi::Abi;
+use syntax::attr;
 use syntax::ast::{NodeId, IntTy, UintTy, FloatTy};
 use syntax::codemap::Span;
+use std::cell::RefCell;
 use std::ffi::CString;
 use std::fs::File;
-use std::io;
-use std::io::Write;
+use std::io::{self, Write};
+use std::iter;
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
@@ -29,133 +38,755 @@ use rustc_data_structures::indexed_vec::Idx;
 
 #[derive(Debug, Clone)]
 pub struct WasmTransOptions {
-    pub optimize: bool,
+    pub opt_level: OptLevel,
+    /// The Binaryen passes run instead of the default pipeline of the optimization level
+    pub passes: Vec<String>,
     pub interpret: bool,
+    /// Interprets the module calling this exported fn, instead of running the start fn
+    pub invoke: Option<Invocation>,
     pub print: bool,
-    trace: bool,
+    /// Traces the Binaryen API calls into a C program building the same module, to reproduce
+    /// Binaryen bugs
+    pub trace: bool,
+    /// Defaults to the binary output path with a `.c` extension
+    pub trace_path: Option<String>,
     pub binary_output_path: Option<String>,
+    /// The artifacts written next to the binary output path. The binary module is also written
+    /// when there is an output path, or a JS loader to run it.
+    pub emit: Vec<EmitKind>,
+    pub panic_strategy: PanicStrategy,
+    pub initial_memory_pages: u32,
+    /// Defaults to the 4GB wasm32 limit, letting the allocator grow the memory
+    pub max_memory_pages: Option<u32>,
+    pub stack_size: u32,
+    pub stack_placement: StackPlacement,
+    /// Defaults to trapping in debug builds, and to no check otherwise
+    pub stack_check: Option<StackCheck>,
+    /// Replaces the fns using unsupported constructs with stubs calling `env.__unsupported` and
+    /// trapping, warning about them instead of failing
+    pub allow_unsupported: bool,
+    /// Reports the fns the validator's errors are in, with the MIR they are translated from
+    pub validate_each_fn: bool,
+    /// Only classifies the MIR of the crate to list its unsupported constructs, printing the
+    /// report instead of translating the module
+    pub report_unsupported: Option<ReportFormat>,
 }
 
 impl WasmTransOptions {
     pub fn new() -> WasmTransOptions {
         WasmTransOptions {
-            optimize: false,
+            opt_level: OptLevel::O0,
+            passes: Vec::new(),
             interpret: false,
+            invoke: None,
             print: true,
             trace: false,
+            trace_path: None,
             binary_output_path: None,
+            emit: Vec::new(),
+            panic_strategy: PanicStrategy::Abort,
+            initial_memory_pages: 256,
+            max_memory_pages: None,
+            stack_size: 64 * 1024,
+            stack_placement: StackPlacement::AfterStatics,
+            stack_check: None,
+            allow_unsupported: false,
+            validate_each_fn: false,
+            report_unsupported: None,
+        }
+    }
+
+    /// The artifacts to write: the `emit` kinds, and the binary module if there's an output path
+    /// or a JS loader.
+    fn emit_kinds(&self) -> Vec<EmitKind> {
+        let mut kinds = self.emit.clone();
+        let wasm = self.binary_output_path.is_some() || kinds.contains(&EmitKind::Js);
+        if wasm && !kinds.contains(&EmitKind::Wasm) {
+            kinds.insert(0, EmitKind::Wasm);
+        }
+        kinds
+    }
+
+    /// The path of an artifact: the binary output path, or the crate name when there is none,
+    /// with the artifact's extension.
+    fn output_path(&self, crate_name: &str, kind: EmitKind) -> PathBuf {
+        let wasm_path = match self.binary_output_path {
+            Some(ref path) => PathBuf::from(path),
+            None => PathBuf::from(crate_name).with_extension("wasm"),
+        };
+        match kind {
+            EmitKind::Wasm => wasm_path,
+            _ => wasm_path.with_extension(kind.extension()),
+        }
+    }
+}
+
+/// An exported fn for the interpreter to call, with its arguments, which are parsed as the types
+/// of its wasm params.
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// The optimization levels, like rustc's, mapped to the Binaryen optimize and shrink levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+    Os,
+    Oz,
+}
+
+impl OptLevel {
+    /// The Binaryen optimize and shrink levels
+    fn binaryen_levels(&self) -> (i32, i32) {
+        match *self {
+            OptLevel::O0 => (0, 0),
+            OptLevel::O1 => (1, 0),
+            OptLevel::O2 => (2, 0),
+            OptLevel::O3 => (3, 0),
+            OptLevel::Os => (2, 1),
+            OptLevel::Oz => (2, 2),
         }
     }
 }
 
+// Removes the fns, imports and globals nothing uses, which the default pipeline keeps. The
+// modules are shipped over the network, so this is done at every optimization level but O0.
+const DEAD_CODE_PASSES: &'static [&'static str] = &["remove-unused-module-elements"];
+
+/// The artifacts `trans_crate` can write: the binary and text formats of the module, a JS loader
+/// running the binary module with the runtime, and the MIR of the crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitKind {
+    Wasm,
+    Wast,
+    Js,
+    Mir,
+}
+
+impl EmitKind {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            EmitKind::Wasm => "wasm",
+            EmitKind::Wast => "wast",
+            EmitKind::Js => "js",
+            EmitKind::Mir => "mir",
+        }
+    }
+}
+
+/// How panics are translated. `Abort` traps at the panic. `Unwind` emulates unwinding: the panic
+/// sets a flag in a global, checked after each call, which runs the cleanup blocks of every
+/// frame before returning to its caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicStrategy {
+    Abort,
+    Unwind,
+}
+
+/// Where the stack, growing downwards, is in linear memory. The heap starts after the statics
+/// and the stack, or at the end of the initial memory when the stack is there, so that allocators
+/// only hand out the pages memory growth adds above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackPlacement {
+    AfterStatics,
+    EndOfMemory,
+}
+
+/// How fn prologues check that the new frame fits between the stack limit and the stack pointer,
+/// instead of overwriting the statics. `Handler` calls the host's `env.__rust_stack_overflow`
+/// before trapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackCheck {
+    Off,
+    Trap,
+    Handler,
+}
+
+/// The format of the report of unsupported constructs. The JSON report groups them by kind, with
+/// their count and the fn and location of each one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+}
+
 pub fn trans_crate<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
                              mir_map: &MirMap<'tcx>,
+                             access_levels: &AccessLevels,
                              entry_fn: Option<NodeId>,
                              options: &WasmTransOptions)
                              -> Result<()> {
 
     let _ignore = tcx.dep_graph.in_ignore();
 
-    if options.trace {
-        unsafe { BinaryenSetAPITracing(true) }
+    // Binaryen aborts on unknown pass names
+    let unknown_pass = options.passes.iter().find(|pass| !builder::PASS_NAMES.contains(&&pass[..]));
+    if let Some(pass) = unknown_pass {
+        return Err(ErrorKind::UnknownPass(pass.clone()).into());
+    }
+
+    let crate_name = tcx.crate_name.to_string();
+    let emit = options.emit_kinds();
+
+    // The MIR is written first, to be available when its translation fails
+    if emit.contains(&EmitKind::Mir) {
+        let mut file = try!(File::create(options.output_path(&crate_name, EmitKind::Mir)));
+        try!(pretty::write_mir_pretty(*tcx, mir_map.map.keys().cloned(), mir_map, &mut file));
+    }
+
+    // The report only classifies the constructs of the MIR bodies, nothing is translated or
+    // traced, so it's not printed into the trace
+    if let Some(format) = options.report_unsupported {
+        let unsupported_fns = find_unsupported_fns(tcx, mir_map);
+        match format {
+            ReportFormat::Json => println!("{}", unsupported_report_json(tcx, &unsupported_fns)),
+        }
+        return Ok(());
     }
 
+    // Until dropped, once the module is optimized. The trace is also complete when translation
+    // fails, e.g. when the module is invalid. Binaryen prints it to stdout, which is redirected
+    // to the trace file meanwhile: nothing else is printed before it's dropped.
+    let trace = if options.trace {
+        let path = match options.trace_path {
+            Some(ref path) => PathBuf::from(path),
+            None => options.output_path(&crate_name, EmitKind::Wasm).with_extension("c"),
+        };
+        debug!("tracing the binaryen API calls into {}", path.display());
+        let file = try!(File::create(path));
+        Some(try!(builder::ApiTrace::new(&file)))
+    } else {
+        None
+    };
+
+    let mut module = builder::Module::new();
+    // the stack pointer is set by the runtime init fn, once the memory layout is known
+    let zero = unsafe { BinaryenConst(module.module, BinaryenLiteralInt32(0)) };
+    let stack_pointer =
+        module.create_global(STACK_POINTER_GLOBAL, builder::ReprType::Int32, true, zero);
+    let zero = unsafe { BinaryenConst(module.module, BinaryenLiteralInt32(0)) };
+    let panic_flag =
+        module.create_global(PANIC_FLAG_GLOBAL, builder::ReprType::Int32, true, zero);
+    let zero = unsafe { BinaryenConst(module.module, BinaryenLiteralInt32(0)) };
+    let stack_limit =
+        module.create_global(STACK_LIMIT_GLOBAL, builder::ReprType::Int32, true, zero);
+
+    let stack_check = match options.stack_check {
+        Some(stack_check) => stack_check,
+        None if tcx.sess.opts.debug_assertions => StackCheck::Trap,
+        None => StackCheck::Off,
+    };
+
+    let unsupported_mode = if options.allow_unsupported {
+        UnsupportedMode::Stub
+    } else {
+        UnsupportedMode::Error
+    };
+
     let ref mut v = BinaryenModuleCtxt {
         tcx: tcx,
         mir_map: mir_map,
-        module: builder::Module::new(),
+        access_levels: access_levels,
+        module: module,
         entry_fn: entry_fn,
         fun_types: HashMap::new(),
         fun_names: HashMap::new(),
+        fn_refs: HashMap::new(),
+        fn_table: Vec::new(),
+        vtables: HashMap::new(),
+        static_data: Vec::new(),
+        static_strs: HashMap::new(),
+        runtime_fns: HashMap::new(),
         c_strings: Vec::new(),
+        exports: HashMap::new(),
+        panic_strategy: options.panic_strategy,
+        stack_pointer: stack_pointer,
+        panic_flag: panic_flag,
+        stack_limit: stack_limit,
+        stack_check: stack_check,
+        unsupported_mode: unsupported_mode,
+        unsupported_fns: Vec::new(),
+        unsupported_count: 0,
+        errors: RefCell::new(Vec::new()),
     };
 
     v.module.auto_drop();
 
-    // TODO: allow for a configurable (or auto-detected) memory size
-    let mem_size = BinaryenIndex(256);
+    tcx.map.krate().visit_all_items(v);
+
+    // Unsupported constructs are reported as errors, after translating every fn to find them all
+    if unsupported_mode == UnsupportedMode::Error {
+        if let Some(error) = v.errors.borrow_mut().drain(..).next() {
+            return Err(error);
+        }
+    }
+    if v.unsupported_count > 0 {
+        return Err(ErrorKind::UnsupportedMir(v.unsupported_count).into());
+    }
+
+    // The memory layout is only known once every static has been translated
+    let layout = try!(MemoryLayout::new(options, v.static_data.len() as u32));
+    debug!("memory layout: {:?}", layout);
+
+    // Libraries have no entry fn to start, the host calls `__rust_init` before their exports
+    let is_executable = tcx.sess.crate_types.borrow().contains(&config::CrateTypeExecutable);
+    v.generate_runtime_init(&layout, !is_executable);
+
     unsafe {
+        if !v.fn_table.is_empty() {
+            let table: Vec<_> = v.fn_table.iter().map(|name| v.fn_refs[name]).collect();
+            BinaryenSetFunctionTable(v.module.module,
+                                     table.as_ptr(),
+                                     BinaryenIndex(table.len() as _));
+        }
+
+        // Static data is only known once every fn has been translated, so its segment has to
+        // be added last.
+        let segments = [v.static_data.as_ptr() as *const c_char];
+        let offsets = [BinaryenConst(v.module.module,
+                                     BinaryenLiteralInt32(STATIC_DATA_BASE as i32))];
+        let sizes = [BinaryenIndex(v.static_data.len() as _)];
+        let segment_count = if v.static_data.is_empty() { 0 } else { segments.len() };
+        let memory_name = CString::new("memory").unwrap();
         BinaryenSetMemory(v.module.module,
-                          mem_size,
-                          mem_size,
-                          CString::new("memory").unwrap().as_ptr(),
-                          ptr::null(),
-                          ptr::null(),
-                          ptr::null(),
-                          BinaryenIndex(0));
+                          BinaryenIndex(layout.initial_pages),
+                          BinaryenIndex(layout.max_pages),
+                          memory_name.as_ptr(),
+                          segments.as_ptr(),
+                          offsets.as_ptr(),
+                          sizes.as_ptr(),
+                          BinaryenIndex(segment_count as _));
     }
 
-    tcx.map.krate().visit_all_items(v);
-
-    assert!(v.module.is_valid(),
-            "Internal compiler error: invalid generated module");
+    // The module is only validated once every fn is added, as the calls to the fns translated
+    // later are invalid until then
+    if let Some(errors) = try!(v.module.validate()) {
+        try!(io::stderr().write_all(errors.as_bytes()));
+        if options.validate_each_fn {
+            if let Some(error) = v.report_invalid_fns(&errors) {
+                return Err(error);
+            }
+        }
+        return Err(ErrorKind::InvalidModule.into());
+    }
 
-    // TODO: check which of the Binaryen optimization passes we want aren't on by default here.
-    //       eg, removing unused functions and imports, minification, etc
-    if options.optimize {
+    let (optimize_level, shrink_level) = options.opt_level.binaryen_levels();
+    builder::set_optimize_levels(optimize_level, shrink_level);
+    if !options.passes.is_empty() {
+        debug!("running binaryen passes {:?}", options.passes);
+        v.module.run_passes(&options.passes[..]);
+    } else if options.opt_level != OptLevel::O0 {
+        debug!("optimizing at {:?}", options.opt_level);
         v.module.optimize();
+        v.module.run_passes(DEAD_CODE_PASSES);
     }
 
-    unsafe {
-        if options.trace {
-            BinaryenSetAPITracing(false);
-        }
+    drop(trace);
 
-        if options.print && !options.interpret {
+    let interpret = options.interpret || options.invoke.is_some();
+    unsafe {
+        if options.print && !interpret {
             BinaryenModulePrint(v.module.module);
         }
 
-        if options.interpret {
+        if options.interpret && options.invoke.is_none() {
             BinaryenModuleInterpret(v.module.module);
         }
     }
 
-    for output in &options.binary_output_path {
-        v.write_to_file(output).expect("error writing wasm file");
+    for &kind in &emit {
+        let path = options.output_path(&crate_name, kind);
+        match kind {
+            EmitKind::Wasm => try!(v.write_to_file(&path)),
+            EmitKind::Wast => {
+                let file = try!(File::create(&path));
+                try!(v.module.print_to_file(&file));
+            }
+            EmitKind::Js => {
+                let wasm_path = options.output_path(&crate_name, EmitKind::Wasm);
+                try!(write_js_loader(&path, &wasm_path));
+            }
+            EmitKind::Mir => {}
+        }
+    }
+
+    // The invocation replaces the start fn, so it's only added once the module is written. The
+    // interpreter aborts on traps.
+    if let Some(ref invocation) = options.invoke {
+        try!(v.generate_invocation(invocation));
+        unsafe { BinaryenModuleInterpret(v.module.module) }
     }
 
     Ok(())
 }
 
+/// Writes the JS runtime, running the binary module at `wasm_path` under d8.
+fn write_js_loader(path: &Path, wasm_path: &Path) -> Result<()> {
+    let wasm_path = wasm_path.to_str().expect("non UTF-8 output path");
+    let mut file = try!(File::create(path));
+    try!(writeln!(file, "var wasm_path = {};", json_string(wasm_path)));
+    try!(file.write_all(include_str!("../rt/rustrt.js").as_bytes()));
+    Ok(())
+}
+
 struct BinaryenModuleCtxt<'v, 'tcx: 'v> {
     tcx: &'v TyCtxt<'v, 'tcx, 'tcx>,
     mir_map: &'v MirMap<'tcx>,
+    access_levels: &'v AccessLevels,
     module: builder::Module,
     entry_fn: Option<NodeId>,
     fun_types: HashMap<ty::FnSig<'tcx>, BinaryenFunctionTypeRef>,
     fun_names: HashMap<(DefId, ty::FnSig<'tcx>), CString>,
+    fn_refs: HashMap<String, BinaryenFunctionRef>,
+    fn_table: Vec<String>,
+    vtables: HashMap<ty::PolyTraitRef<'tcx>, u32>,
+    static_data: Vec<u8>,
+    /// The addresses of the strings in the static data, like the panic messages and file names
+    static_strs: HashMap<String, u32>,
+    runtime_fns: HashMap<RuntimeFn, CString>,
     c_strings: Vec<CString>,
+    exports: HashMap<String, ExportedFn>,
+    panic_strategy: PanicStrategy,
+    stack_pointer: builder::Global,
+    panic_flag: builder::Global,
+    stack_limit: builder::Global,
+    stack_check: StackCheck,
+    unsupported_mode: UnsupportedMode,
+    /// The fns replaced by stubs, indexed by the id they pass to `env.__unsupported`
+    unsupported_fns: Vec<UnsupportedFn>,
+    /// The number of unsupported constructs reported as errors
+    unsupported_count: usize,
+    /// Layout and trait selection failures, the first one is returned by `trans_crate`
+    errors: RefCell<Vec<Error>>,
 }
 
 impl<'v, 'tcx: 'v> BinaryenModuleCtxt<'v, 'tcx> {
-    fn serialize(&self) -> Vec<u8> {
+    /// The binary format of the module. Binaryen can't tell its size beforehand, so it's written
+    /// again into a buffer twice as large until it fits.
+    fn serialize(&self) -> Result<Vec<u8>> {
+        let mut capacity = INITIAL_SERIALIZATION_BUFFER_SIZE;
+        loop {
+            let mut buffer = Vec::with_capacity(capacity);
+            let size = unsafe {
+                BinaryenModuleWrite(self.module.module,
+                                    mem::transmute(buffer.as_mut_ptr()),
+                                    buffer.capacity())
+            };
+
+            // the output is truncated when it fills the whole buffer
+            if size < buffer.capacity() {
+                unsafe { buffer.set_len(size) };
+                buffer.shrink_to_fit();
+                return Ok(buffer);
+            }
+
+            debug!("the module doesn't fit in a {} bytes serialization buffer", capacity);
+            if capacity >= MAX_SERIALIZATION_BUFFER_SIZE {
+                return Err(ErrorKind::SerializationOverflow(capacity).into());
+            }
+            capacity *= 2;
+        }
+    }
+
+    /// The `__rust_init` fn setting up the stack, called by the runtime start fn of executables,
+    /// and exported by reactor modules. Also emits the heap base fn, if the allocator uses it.
+    fn generate_runtime_init(&mut self, layout: &MemoryLayout, export: bool) {
+        debug!("emitting runtime init fn, exported: {}", export);
+        let runtime_init_name = CString::new(RUNTIME_INIT_NAME).expect("");
+        let runtime_init_name_ptr = runtime_init_name.as_ptr();
+        self.c_strings.push(runtime_init_name);
+
+        unsafe {
+            let module = self.module.module;
+            let runtime_init_ty = BinaryenAddFunctionType(module,
+                                                          runtime_init_name_ptr,
+                                                          BinaryenNone(),
+                                                          ptr::null_mut(),
+                                                          BinaryenIndex(0));
+            let stack_top = BinaryenConst(module, BinaryenLiteralInt32(layout.stack_top as i32));
+            let stack_base = BinaryenConst(module,
+                                           BinaryenLiteralInt32(layout.stack_base as i32));
+            let stack_init = [self.stack_pointer.set(&self.module, stack_top),
+                              self.stack_limit.set(&self.module, stack_base)];
+            let body = BinaryenBlock(module,
+                                     ptr::null(),
+                                     stack_init.as_ptr(),
+                                     BinaryenIndex(stack_init.len() as _));
+            BinaryenAddFunction(module,
+                                runtime_init_name_ptr,
+                                runtime_init_ty,
+                                ptr::null_mut(),
+                                BinaryenIndex(0),
+                                body);
+            if export {
+                BinaryenAddExport(module, runtime_init_name_ptr, runtime_init_name_ptr);
+            }
+
+            if let Some(heap_base_name) = self.runtime_fns.get(&RuntimeFn::HeapBase) {
+                let heap_base_ty = BinaryenAddFunctionType(module,
+                                                           heap_base_name.as_ptr(),
+                                                           BinaryenInt32(),
+                                                           ptr::null_mut(),
+                                                           BinaryenIndex(0));
+                let heap_base = BinaryenLiteralInt32(layout.heap_base as i32);
+                BinaryenAddFunction(module,
+                                    heap_base_name.as_ptr(),
+                                    heap_base_ty,
+                                    ptr::null_mut(),
+                                    BinaryenIndex(0),
+                                    BinaryenConst(module, heap_base));
+            }
+        }
+    }
+
+    /// Replaces the start fn with a call to `__rust_init`, then to the invoked fn, printing its
+    /// result with the interpreter's `spectest.print`.
+    fn generate_invocation(&mut self, invocation: &Invocation) -> Result<()> {
+        debug!("emitting invocation {:?}", invocation);
+        let (fn_name_ptr, params, ret) = match self.exports.get(&invocation.name) {
+            Some(export) => (export.fn_name.as_ptr(), export.params.clone(), export.ret),
+            None => return Err(format!("no exported fn `{}` to invoke", invocation.name).into()),
+        };
+        if params.len() != invocation.args.len() {
+            return Err(format!("`{}` takes {} arguments, but {} were given",
+                               invocation.name,
+                               params.len(),
+                               invocation.args.len())
+                .into());
+        }
+
+        let module = self.module.module;
+        let mut args = Vec::new();
+        for (&ty, arg) in params.iter().zip(&invocation.args) {
+            let (literal, ty_name) = unsafe {
+                if ty == BinaryenInt32() {
+                    (arg.parse().ok().map(|value| BinaryenLiteralInt32(value)), "i32")
+                } else if ty == BinaryenInt64() {
+                    (arg.parse().ok().map(|value| BinaryenLiteralInt64(value)), "i64")
+                } else if ty == BinaryenFloat32() {
+                    (arg.parse().ok().map(|value| BinaryenLiteralFloat32(value)), "f32")
+                } else {
+                    (arg.parse().ok().map(|value| BinaryenLiteralFloat64(value)), "f64")
+                }
+            };
+            match literal {
+                Some(literal) => args.push(unsafe { BinaryenConst(module, literal) }),
+                None => {
+                    return Err(format!("invalid argument `{}` for an {} param of `{}`",
+                                       arg,
+                                       ty_name,
+                                       invocation.name)
+                        .into())
+                }
+            }
+        }
+
+        let invoke_name = CString::new("__rust_invoke").expect("");
+        let print_name = CString::new("__rust_invoke_print").expect("");
+        let runtime_init_name = CString::new(RUNTIME_INIT_NAME).expect("");
         unsafe {
-            // TODO: find a way to determine the size of the buffer
-            // first. Right now we just make a 4MB buffer and
-            // truncate.
-            let mut buffer = Vec::with_capacity(1 << 22);
-            let size = BinaryenModuleWrite(self.module.module,
-                                           mem::transmute(buffer.as_mut_ptr()),
-                                           buffer.capacity());
+            let call = BinaryenCall(module,
+                                    fn_name_ptr,
+                                    args.as_ptr(),
+                                    BinaryenIndex(args.len() as _),
+                                    ret);
+            let call = if ret == BinaryenNone() {
+                call
+            } else {
+                let spectest = CString::new("spectest").expect("");
+                let print = CString::new("print").expect("");
+                let print_ty = BinaryenAddFunctionType(module,
+                                                       print_name.as_ptr(),
+                                                       BinaryenNone(),
+                                                       &ret,
+                                                       BinaryenIndex(1));
+                BinaryenAddImport(module,
+                                  print_name.as_ptr(),
+                                  spectest.as_ptr(),
+                                  print.as_ptr(),
+                                  print_ty);
+                let results = [call];
+                BinaryenCallImport(module,
+                                   print_name.as_ptr(),
+                                   results.as_ptr(),
+                                   BinaryenIndex(results.len() as _),
+                                   BinaryenNone())
+            };
+
+            let statements = [BinaryenCall(module,
+                                           runtime_init_name.as_ptr(),
+                                           ptr::null(),
+                                           BinaryenIndex(0),
+                                           BinaryenNone()),
+                              call];
+            let body = BinaryenBlock(module,
+                                     ptr::null(),
+                                     statements.as_ptr(),
+                                     BinaryenIndex(statements.len() as _));
+            let invoke_ty = BinaryenAddFunctionType(module,
+                                                    invoke_name.as_ptr(),
+                                                    BinaryenNone(),
+                                                    ptr::null_mut(),
+                                                    BinaryenIndex(0));
+            let invoke = BinaryenAddFunction(module,
+                                             invoke_name.as_ptr(),
+                                             invoke_ty,
+                                             ptr::null_mut(),
+                                             BinaryenIndex(0),
+                                             body);
+            BinaryenSetStart(module, invoke);
+        }
+        self.c_strings.push(invoke_name);
+        self.c_strings.push(print_name);
+        self.c_strings.push(runtime_init_name);
+
+        Ok(())
+    }
+
+    /// Reports the translated fns the validator's errors are in, with the MIR they are translated
+    /// from, and returns the error of the first one.
+    fn report_invalid_fns(&self, validator_errors: &str) -> Option<Error> {
+        let marker = "[wasm-validator error in function ";
+        let mut wasm_names: Vec<&str> = Vec::new();
+        for line in validator_errors.lines() {
+            if let Some(start) = line.find(marker) {
+                let name = line[start + marker.len()..].split(']').next().expect("");
+                if !wasm_names.contains(&name) {
+                    wasm_names.push(name);
+                }
+            }
+        }
 
-            buffer.set_len(size);
-            buffer.shrink_to_fit();
+        let mut first_error = None;
+        for wasm_name in wasm_names {
+            // the runtime fns have no MIR
+            if !self.fn_refs.contains_key(wasm_name) {
+                continue;
+            }
+            let did = match self.fun_names
+                .iter()
+                .find(|&(_, name)| name.to_str() == Ok(wasm_name)) {
+                Some((&(did, _), _)) => did,
+                None => continue,
+            };
 
-            buffer
+            let path = self.tcx.item_path_str(did);
+            let mut mir = Vec::new();
+            pretty::write_mir_pretty(*self.tcx, iter::once(did), self.mir_map, &mut mir)
+                .expect("error writing MIR to a Vec");
+            let message = format!("invalid wasm fn `{}`, translated from `{}`, see the \
+                                   validator's errors above. Its MIR:\n{}",
+                                  wasm_name,
+                                  path,
+                                  String::from_utf8_lossy(&mir));
+            self.tcx.sess.span_err(self.mir_map.map[&did].span, &message);
+            if first_error.is_none() {
+                first_error = Some(ErrorKind::InvalidFn(path).into());
+            }
         }
+        first_error
     }
 
-    fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let buffer = try!(self.serialize());
         let mut file = try!(File::create(path));
-        let buffer = self.serialize();
 
-        file.write_all(buffer.as_slice())
+        Ok(try!(file.write_all(buffer.as_slice())))
     }
 }
 
-// The address in wasm linear memory where we store the stack pointer
-// TODO: investigate where should the preferred location be
-const STACK_POINTER_ADDRESS: i32 = 0;
+// The mutable global holding the shadow stack pointer
+const STACK_POINTER_GLOBAL: &'static str = "__stack_pointer";
+
+// The mutable global holding the lowest address of the stack, checked by fn prologues
+const STACK_LIMIT_GLOBAL: &'static str = "__stack_limit";
+
+// The mutable global holding the flag set by panics, when emulating unwinding
+const PANIC_FLAG_GLOBAL: &'static str = "__rust_panicking";
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+// The low page of linear memory is never allocated, so that null pointers don't alias any data.
+// Debug builds check the pointers of the loads and stores of translated fns against it, but not
+// the ones of the runtime fns, like memmove.
+const GUARD_REGION_SIZE: u32 = WASM_PAGE_SIZE as u32;
+
+// The address in wasm linear memory where static data, like vtables, starts
+const STATIC_DATA_BASE: u32 = GUARD_REGION_SIZE;
+
+// The wasm32 limit of 4GB of linear memory
+const MAX_MEMORY_PAGES: u32 = 64 * 1024;
+
+// The alignment of the stack pointer, and so of every frame
+const STACK_ALIGN: u64 = 16;
+
+// The size of the first buffer the binary module is written into, doubled until it fits
+const INITIAL_SERIALIZATION_BUFFER_SIZE: usize = 1 << 22;
+
+// 1GB, a limit no module should reach, which still fits in a 32-bit usize
+const MAX_SERIALIZATION_BUFFER_SIZE: usize = 1 << 30;
+
+// The fn setting up the stack, before any translated code runs
+const RUNTIME_INIT_NAME: &'static str = "__rust_init";
+
+/// The regions of linear memory: the guard page, the statics, then the stack and the heap, in the
+/// order given by the `StackPlacement`.
+#[derive(Debug)]
+struct MemoryLayout {
+    initial_pages: u32,
+    max_pages: u32,
+    static_end: u32,
+    stack_base: u32,
+    stack_top: u32,
+    heap_base: u32,
+}
+
+impl MemoryLayout {
+    fn new(options: &WasmTransOptions, static_size: u32) -> Result<MemoryLayout> {
+        let align = |address: u64| (address + STACK_ALIGN - 1) & !(STACK_ALIGN - 1);
+        let memory_size = options.initial_memory_pages as u64 * WASM_PAGE_SIZE;
+        let stack_size = align(options.stack_size as u64);
+        let static_end = STATIC_DATA_BASE as u64 + static_size as u64;
+
+        let (stack_base, heap_base) = match options.stack_placement {
+            StackPlacement::AfterStatics => {
+                let stack_base = align(static_end);
+                (stack_base, stack_base + stack_size)
+            }
+            StackPlacement::EndOfMemory => (memory_size.saturating_sub(stack_size), memory_size),
+        };
+        let stack_top = stack_base + stack_size;
+        if stack_base < static_end || stack_top > memory_size || heap_base > memory_size {
+            return Err(format!("{} bytes of statics and a {} bytes stack don't fit in {} pages \
+                                of initial memory",
+                               static_size,
+                               stack_size,
+                               options.initial_memory_pages)
+                .into());
+        }
+
+        let max_pages = options.max_memory_pages.unwrap_or(MAX_MEMORY_PAGES);
+        if max_pages < options.initial_memory_pages || max_pages > MAX_MEMORY_PAGES {
+            return Err(format!("invalid maximum memory of {} pages, for {} initial pages",
+                               max_pages,
+                               options.initial_memory_pages)
+                .into());
+        }
+
+        Ok(MemoryLayout {
+            initial_pages: options.initial_memory_pages,
+            max_pages: max_pages,
+            static_end: static_end as u32,
+            stack_base: stack_base as u32,
+            stack_top: stack_top as u32,
+            heap_base: heap_base as u32,
+        })
+    }
+}
 
 impl<'v, 'tcx> Visitor<'v> for BinaryenModuleCtxt<'v, 'tcx> {
     fn visit_fn(&mut self, fk: FnKind<'v>, fd: &'v FnDecl, b: &'v Block, s: Span, id: NodeId) {
@@ -175,15 +806,38 @@ impl<'v, 'tcx> Visitor<'v> for BinaryenModuleCtxt<'v, 'tcx> {
             let mut ctxt = BinaryenFnCtxt {
                 tcx: self.tcx,
                 mir_map: self.mir_map,
+                access_levels: self.access_levels,
                 mir: mir,
                 did: did,
                 sig: &sig,
+                substs: Substs::empty(*self.tcx),
                 func: self.module.create_func(),
                 entry_fn: self.entry_fn,
                 fun_types: &mut self.fun_types,
                 fun_names: &mut self.fun_names,
+                fn_refs: &mut self.fn_refs,
+                fn_table: &mut self.fn_table,
+                vtables: &mut self.vtables,
+                static_data: &mut self.static_data,
+                static_strs: &mut self.static_strs,
+                runtime_fns: &mut self.runtime_fns,
                 c_strings: &mut self.c_strings,
+                exports: &mut self.exports,
+                panic_strategy: self.panic_strategy,
+                stack_pointer: &self.stack_pointer,
+                panic_flag: &self.panic_flag,
+                stack_limit: &self.stack_limit,
+                stack_check: self.stack_check,
+                unsupported_mode: self.unsupported_mode,
+                unsupported_fns: &mut self.unsupported_fns,
+                unsupported_count: &mut self.unsupported_count,
+                errors: &self.errors,
+                frame: None,
+                span: mir.span,
+                unsupported: RefCell::new(Vec::new()),
                 checked_op_local: None,
+                guard_ptr_local: None,
+                arg_map: Vec::new(),
                 var_map: Vec::new(),
                 temp_map: Vec::new(),
                 ret_var: None,
@@ -199,15 +853,41 @@ impl<'v, 'tcx> Visitor<'v> for BinaryenModuleCtxt<'v, 'tcx> {
 struct BinaryenFnCtxt<'v, 'tcx: 'v, 'module> {
     tcx: &'v TyCtxt<'v, 'tcx, 'tcx>,
     mir_map: &'v MirMap<'tcx>,
+    access_levels: &'v AccessLevels,
     mir: &'v Mir<'tcx>,
     did: DefId,
     sig: &'v FnSig<'tcx>,
+    /// The substitutions of the fn instance being translated, for the types of its MIR
+    substs: &'tcx Substs<'tcx>,
     func: builder::Fn<'module>,
     entry_fn: Option<NodeId>,
     fun_types: &'v mut HashMap<ty::FnSig<'tcx>, BinaryenFunctionTypeRef>,
     fun_names: &'v mut HashMap<(DefId, ty::FnSig<'tcx>), CString>,
+    fn_refs: &'v mut HashMap<String, BinaryenFunctionRef>,
+    fn_table: &'v mut Vec<String>,
+    vtables: &'v mut HashMap<ty::PolyTraitRef<'tcx>, u32>,
+    static_data: &'v mut Vec<u8>,
+    static_strs: &'v mut HashMap<String, u32>,
+    runtime_fns: &'v mut HashMap<RuntimeFn, CString>,
     c_strings: &'v mut Vec<CString>,
+    exports: &'v mut HashMap<String, ExportedFn>,
+    panic_strategy: PanicStrategy,
+    stack_pointer: &'v builder::Global,
+    panic_flag: &'v builder::Global,
+    stack_limit: &'v builder::Global,
+    stack_check: StackCheck,
+    unsupported_mode: UnsupportedMode,
+    unsupported_fns: &'v mut Vec<UnsupportedFn>,
+    unsupported_count: &'v mut usize,
+    errors: &'v RefCell<Vec<Error>>,
+    frame: Option<Frame>,
+    /// The span of the statement or terminator being translated
+    span: Span,
+    unsupported: RefCell<Vec<Unsupported>>,
     checked_op_local: Option<BinaryenIndex>,
+    /// In debug builds, the local holding the pointers checked against the guard region
+    guard_ptr_local: Option<BinaryenIndex>,
+    arg_map: Vec<Option<usize>>,
     var_map: Vec<Option<usize>>,
     temp_map: Vec<Option<usize>>,
     ret_var: Option<usize>,
@@ -231,19 +911,21 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
 
         debug!("translating fn {:?}", self.tcx.item_path_str(self.did));
 
-        // Translate arg and ret tys to wasm
+        // Translate arg and ret tys to wasm. Zero-sized args and returns have no wasm
+        // counterpart.
         for ty in &self.sig.inputs {
-            self.func.add_arg(rust_ty_to_builder(ty).unwrap());
+            match self.local_ty(ty) {
+                Some(ty) => {
+                    let arg = self.func.add_arg(ty).index();
+                    self.arg_map.push(Some(arg))
+                }
+                None => self.arg_map.push(None),
+            }
         }
-        let mut needs_ret_var = false;
         let ret_ty = self.sig.output;
         debug!("ret_ty is {:?}", ret_ty);
-        let binaryen_ret = if !ret_ty.is_nil() && !ret_ty.is_never() {
-            needs_ret_var = true;
-            rust_ty_to_builder(ret_ty)
-        } else {
-            None
-        };
+        let binaryen_ret = self.local_ty(ret_ty);
+        let needs_ret_var = binaryen_ret.is_some();
         debug!("needs_ret_var = {:?}", needs_ret_var);
 
         // Create the wasm vars.
@@ -251,7 +933,7 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
 
         for mir_var in &self.mir.var_decls {
             debug!("adding local {:?}", mir_var);
-            match rust_ty_to_builder(mir_var.ty) {
+            match self.local_ty(mir_var.ty) {
                 Some(ty) => {
                     let var = self.func.create_local(ty).index();
                     self.var_map.push(Some(var))
@@ -262,19 +944,22 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
 
         for mir_var in &self.mir.temp_decls {
             debug!("adding {:?}", mir_var);
-            let ty = rust_ty_to_builder(mir_var.ty).map(|ty| self.func.create_local(ty).index());
+            let ty = self.local_ty(mir_var.ty).map(|ty| self.func.create_local(ty).index());
             debug!("type is {:?}", &ty);
             self.temp_map.push(ty);
         }
 
         if needs_ret_var {
             debug!("adding ret var");
-            self.ret_var =
-                Some(self.func.create_local(rust_ty_to_builder(ret_ty).unwrap()).index());
+            self.ret_var = binaryen_ret.map(|ty| self.func.create_local(ty).index());
         }
 
-        // Function prologue: stack pointer local
-        let stack_pointer_local = self.func.create_local(builder::ReprType::Int32).index();
+        // Function prologue: frame base local, and the frame slots of the locals living in linear
+        // memory
+        let frame_base_local = self.func.create_local(builder::ReprType::Int32).index();
+        let frame = self.layout_frame(frame_base_local.into());
+        debug!("frame of {} bytes, slots: {:?}", frame.size, frame.slots);
+        self.frame = Some(frame);
 
         // relooper helper local for irreducible control flow
         let relooper_local = self.func.create_local(builder::ReprType::Int32).index();
@@ -284,17 +969,114 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
         assert!(self.func.get_var(checked_op_local).ty() == builder::ReprType::Int64);
         self.checked_op_local = Some(checked_op_local.into());
 
-        let locals_count = self.sig.inputs.len() + self.func.num_locals();
+        // guard region check local, for the pointer of a load or store
+        if self.tcx.sess.opts.debug_assertions {
+            let guard_ptr_local = self.func.create_local(builder::ReprType::Int32).index();
+            self.guard_ptr_local = Some(guard_ptr_local.into());
+        }
+
+        let locals_count = self.func.num_args() + self.func.num_locals();
         debug!(concat!("{} wasm locals initially found - params: {}, vars: {} ",
-                       "(incl. stack pointer helper ${}, relooper helper ${}, ",
+                       "(incl. frame base helper ${}, relooper helper ${}, ",
                        "checked operation helper ${})"),
                locals_count,
-               self.sig.inputs.len(),
+               self.func.num_args(),
                self.func.num_vars(),
-               stack_pointer_local.index(),
+               frame_base_local.index(),
                relooper_local.index(),
                checked_op_local.index());
 
+        // Panic entry fns are replaced by the report of the panic to the host: their MIR body is
+        // not translated
+        let panic_entry = self.panic_entry();
+        let body = match panic_entry {
+            Some(panic_entry) => {
+                debug!("emitting {:?} panic entry fn {:?}",
+                       panic_entry,
+                       self.tcx.item_path_str(self.did));
+                self.trans_panic_entry(panic_entry)
+            }
+            None => self.trans_blocks(relooper_local.into()),
+        };
+
+        if !self.fun_types.contains_key(self.sig) {
+            let name = format!("rustfn-{}-{}", self.did.krate, self.did.index.as_u32());
+            let name = CString::new(name).expect("");
+            let name_ptr = name.as_ptr();
+            self.c_strings.push(name);
+            let name = &self.c_strings[self.c_strings.len() - 1];
+            let ty = self.func.create_sig_type(name, binaryen_ret);
+            self.fun_types.insert(self.sig.clone(), ty);
+        }
+
+        let nid = self.tcx.map.as_local_node_id(self.did).expect("");
+
+        unsafe {
+            let body = if self.unsupported.borrow().is_empty() {
+                body
+            } else if self.unsupported_mode == UnsupportedMode::Error {
+                self.report_unsupported(None);
+                *self.unsupported_count += self.unsupported.borrow().len();
+                debug!("not emitting fn {:?}, which has unsupported constructs",
+                       self.tcx.item_path_str(self.did));
+                return;
+            } else {
+                let fn_id = self.unsupported_fns.len();
+                self.report_unsupported(Some(fn_id));
+                self.unsupported_fns.push(UnsupportedFn {
+                    path: self.tcx.item_path_str(self.did),
+                    constructs: self.unsupported.borrow_mut().drain(..).collect(),
+                });
+                self.emit_unsupported_stub(fn_id)
+            };
+
+            // TODO(eholk): builderize this.
+            let var_types = self.func.binaryen_var_types();
+            let func_ref = BinaryenAddFunction(self.func.module.module,
+                                               fn_name_ptr,
+                                               *self.fun_types.get(self.sig).unwrap(),
+                                               var_types.as_ptr(),
+                                               var_types.len().into(),
+                                               body);
+
+            if panic_entry.is_none() {
+                if let Some(export_name) = self.export_name() {
+                    debug!("exporting fn {:?} as {}",
+                           self.tcx.item_path_str(self.did),
+                           export_name);
+                    self.exports.insert(export_name.clone(),
+                                        ExportedFn {
+                                            fn_name: self.fun_names[&(self.did, self.sig.clone())]
+                                                .clone(),
+                                            params: self.func.binaryen_arg_types(),
+                                            ret: binaryen_ret.into(),
+                                        });
+                    let export_name = self.c_str(&export_name);
+                    BinaryenAddExport(self.func.module.module, fn_name_ptr, export_name);
+                }
+            }
+
+            // keep track of the fn ref for the function table
+            let fn_name = self.fun_names[&(self.did, self.sig.clone())].to_str().expect("");
+            let fn_name = fn_name.to_owned();
+            self.fn_refs.insert(fn_name, func_ref);
+
+            if self.entry_fn == Some(nid) {
+                let is_start = self.mir.arg_decls.len() == 2;
+                let entry_fn_name = if is_start { "start" } else { "main" };
+                let wasm_start = self.generate_runtime_start(&entry_fn_name);
+                debug!("emitting wasm Start fn into entry_fn {:?}",
+                       self.tcx.item_path_str(self.did));
+                BinaryenSetStart(self.func.module.module, wasm_start);
+            }
+        }
+
+        debug!("done translating fn {:?}\n",
+               self.tcx.item_path_str(self.did));
+    }
+
+    /// Translates the basic blocks of the MIR body, and ties them together with the relooper.
+    fn trans_blocks(&mut self, relooper_local: BinaryenIndex) -> BinaryenExpressionRef {
         // Create the relooper for tying together basic blocks. We're
         // going to first translate the basic blocks without the
         // terminators, then go back over the basic blocks and use the
@@ -303,6 +1085,9 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
 
         let mut relooper_blocks = Vec::new();
 
+        // Whether each block ends with a call which can panic, for the unwinding edges
+        let mut calls_can_panic = Vec::new();
+
         debug!("{} MIR basic blocks to translate",
                self.mir.basic_blocks().len());
 
@@ -311,57 +1096,60 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
 
             let mut binaryen_stmts = Vec::new();
             for stmt in &bb.statements {
+                self.span = stmt.source_info.span;
                 match stmt.kind {
                     StatementKind::Assign(ref lvalue, ref rvalue) => {
                         self.trans_assignment(lvalue, rvalue, &mut binaryen_stmts);
                     }
                     StatementKind::StorageLive(_) => {}
                     StatementKind::StorageDead(_) => {}
-                    _ => panic!("{:?}", stmt.kind),
+                    _ => {
+                        self.unsupported(UnsupportedKind::Statement,
+                                         format!("statement {:?}", stmt.kind));
+                    }
                 }
             }
 
             let mut block_kind = BinaryenBlockKind::Default;
+            let mut call_can_panic = false;
 
             // Some features of MIR terminators tranlate to wasm
             // expressions, some translate to relooper edges. These
             // are the expressions.
+            self.span = bb.terminator().source_info.span;
+            let terminator_start = binaryen_stmts.len();
             match bb.terminator().kind {
                 TerminatorKind::Return => {
-                    // Emit function epilogue:
-                    // TODO: like the prologue, not always necessary
-                    unsafe {
-                        debug!("emitting function epilogue, GetLocal({}) + Store",
-                               (&stack_pointer_local).index());
-                        let read_original_sp = BinaryenGetLocal(self.func.module.module,
-                                                                stack_pointer_local.into(),
-                                                                BinaryenInt32());
-                        let restore_original_sp = BinaryenStore(self.func.module.module,
-                                                                4,
-                                                                0,
-                                                                0,
-                                                                self.emit_sp(),
-                                                                read_original_sp,
-                                                                BinaryenInt32());
-                        binaryen_stmts.push(restore_original_sp);
-                    }
-
                     debug!("emitting Return from fn {:?}",
                            self.tcx.item_path_str(self.did));
-                    let expr = if ret_ty.is_nil() {
-                        BinaryenExpressionRef(ptr::null_mut())
-                    } else {
-                        self.trans_operand(&Operand::Consume(Lvalue::ReturnPointer))
-                    };
-                    let expr = unsafe { BinaryenReturn(self.func.module.module, expr) };
-                    binaryen_stmts.push(expr);
+                    self.emit_return(&mut binaryen_stmts);
+                }
+                TerminatorKind::Resume => {
+                    // when unwinding, this is an edge to the block returning to the caller
+                    if self.panic_strategy == PanicStrategy::Abort {
+                        debug!("emitting Unreachable for Resume");
+                        binaryen_stmts.push(unsafe {
+                            BinaryenUnreachable(self.func.module.module)
+                        });
+                    }
+                }
+                TerminatorKind::Drop { ref location, .. } => {
+                    call_can_panic = self.trans_drop(location, &mut binaryen_stmts);
                 }
                 TerminatorKind::Switch { ref discr, .. } => {
-                    let adt = self.trans_lval(discr).unwrap();
+                    let adt = match self.trans_lval(discr) {
+                        Some(adt) => adt,
+                        None => {
+                            self.unsupported(UnsupportedKind::Terminator,
+                                             format!("Switch on zero-sized {:?}", discr));
+                            BinaryenLvalue::new(self.frame().base_local, None, LvalueExtra::None)
+                        }
+                    };
                     let adt_ty = discr.ty(self.mir, *self.tcx).to_ty(*self.tcx);
 
                     if adt.offset.is_some() {
-                        panic!("unimplemented Switch with offset");
+                        self.unsupported(UnsupportedKind::Terminator,
+                                         format!("Switch with offset"));
                     }
 
                     let adt_layout = self.type_layout(adt_ty);
@@ -374,6 +1162,7 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                 let ptr = BinaryenGetLocal(self.func.module.module,
                                                            adt.index,
                                                            BinaryenInt32());
+                                let ptr = self.emit_checked_ptr(ptr);
                                 BinaryenLoad(self.func.module.module,
                                              discr_size,
                                              0,
@@ -392,7 +1181,11 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                                  BinaryenInt32())
                             }
                         }
-                        _ => panic!("unimplemented discrimant value for Layout {:?}", adt_layout),
+                        _ => {
+                            self.unsupported(UnsupportedKind::Layout,
+                                             format!("discriminant value for Layout {:?}",
+                                                     adt_layout))
+                        }
                     };
 
                     block_kind = BinaryenBlockKind::Switch(discr_val);
@@ -404,23 +1197,39 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                     // passed as i32s. A call to a function returning a struct will require
                     // preparing the output return value space on the caller function's frame, and
                     // the called function will write its return value there to avoid memcpys
-                    if let Some((b_func, b_fnty, call_kind)) = self.trans_fn_name_direct(func) {
-                        let b_args: Vec<_> = args.iter().map(|a| self.trans_operand(a)).collect();
+                    if self.trans_intrinsic_call(func, args, destination, &mut binaryen_stmts) {
+                        debug!("lowered intrinsic call to {:?} inline", func);
+                    } else if let Some((b_fnty, call_kind)) = self.trans_callee(func) {
+                        call_can_panic = true;
+                        let mut b_args = Vec::new();
+                        for arg in args {
+                            // zero-sized args are not passed
+                            if !self.is_zst(arg.ty(self.mir, *self.tcx)) {
+                                b_args.push(self.trans_operand(arg));
+                            }
+                        }
                         let b_call = match call_kind {
-                            BinaryenCallKind::Direct => {
+                            BinaryenCallKind::Direct(b_func) => {
                                 BinaryenCall(self.func.module.module,
                                              b_func,
                                              b_args.as_ptr(),
                                              BinaryenIndex(b_args.len() as _),
                                              b_fnty)
                             }
-                            BinaryenCallKind::Import => {
+                            BinaryenCallKind::Import(b_func) => {
                                 BinaryenCallImport(self.func.module.module,
                                                    b_func,
                                                    b_args.as_ptr(),
                                                    BinaryenIndex(b_args.len() as _),
                                                    b_fnty)
                             }
+                            BinaryenCallKind::Indirect(target, fn_type) => {
+                                BinaryenCallIndirect(self.func.module.module,
+                                                     target,
+                                                     b_args.as_ptr(),
+                                                     BinaryenIndex(b_args.len() as _),
+                                                     fn_type)
+                            }
                         };
 
                         match *destination {
@@ -462,21 +1271,12 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                                                  b_call);
                                             binaryen_stmts.push(set_local);
 
-                                            debug!("tmp - allocating return value in linear \
-                                                    memory to SetLocal({}), size: {:?}",
-                                                   dest.index.0,
-                                                   dest_size);
-                                            let allocation =
-                                                self.emit_alloca(dest.index, dest_size);
+                                            let allocation = self.emit_frame_slot(dest.index);
                                             binaryen_stmts.push(allocation);
 
                                             // TMP - the poor man's memcpy
                                             debug!("tmp - emitting Stores to copy result to \
                                                     stack frame");
-                                            let ptr = BinaryenGetLocal(self.func.module.module,
-                                                                       tmp_dest.into(),
-                                                                       BinaryenInt32());
-                                            let sp = self.emit_read_sp();
                                             let mut bytes_to_copy = dest_size;
                                             let mut offset = 0;
                                             while bytes_to_copy > 0 {
@@ -500,6 +1300,11 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                                         offset: {}",
                                                        size,
                                                        offset);
+                                                let ptr =
+                                                    BinaryenGetLocal(self.func.module.module,
+                                                                     tmp_dest.into(),
+                                                                     BinaryenInt32());
+                                                let ptr = self.emit_checked_ptr(ptr);
                                                 let read_bytes =
                                                     BinaryenLoad(self.func.module.module,
                                                                  size,
@@ -508,12 +1313,17 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                                                  0,
                                                                  ty,
                                                                  ptr);
+                                                let slot =
+                                                    BinaryenGetLocal(self.func.module.module,
+                                                                     dest.index,
+                                                                     BinaryenInt32());
+                                                let slot = self.emit_checked_ptr(slot);
                                                 let copy_bytes =
                                                     BinaryenStore(self.func.module.module,
                                                                   size,
                                                                   offset,
                                                                   0,
-                                                                  sp,
+                                                                  slot,
                                                                   read_bytes,
                                                                   BinaryenInt64());
                                                 binaryen_stmts.push(copy_bytes);
@@ -538,8 +1348,9 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                         }
 
                                         _ => {
-                                            panic!("unimplemented Call returned to Layout {:?}",
-                                                   dest_layout)
+                                            let message = format!("Call returned to Layout {:?}",
+                                                                  dest_layout);
+                                            self.unsupported(UnsupportedKind::Layout, message);
                                         }
                                     }
                                 }
@@ -550,11 +1361,29 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                             }
                         }
                     } else {
-                        panic!("untranslated fn call to {:?}", func)
+                        self.unsupported(UnsupportedKind::Terminator,
+                                         format!("Call to {:?}", func));
                     }
                 },
                 _ => (),
             }
+
+            // Cleanup blocks run while unwinding, with the panic flag set: their calls run with
+            // the flag cleared, so that the fns they call and the remaining drops run to the end,
+            // and a panic while panicking aborts.
+            if bb.is_cleanup && call_can_panic && self.panic_strategy == PanicStrategy::Unwind {
+                debug!("clearing the panic flag around the calls of cleanup block bb{}", i);
+                binaryen_stmts.insert(terminator_start, self.emit_set_panic_flag(false));
+                binaryen_stmts.push(unsafe {
+                    BinaryenIf(self.func.module.module,
+                               self.emit_panic_flag(),
+                               BinaryenUnreachable(self.func.module.module),
+                               BinaryenExpressionRef(ptr::null_mut()))
+                });
+                binaryen_stmts.push(self.emit_set_panic_flag(true));
+                call_can_panic = false;
+            }
+
             unsafe {
                 let name = format!("bb{}", i);
                 let name = CString::new(name).expect("");
@@ -573,11 +1402,34 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                     }
                 };
                 relooper_blocks.push(relooper_block);
+                calls_can_panic.push(call_can_panic);
             }
         }
 
-        // Create the relooper edges from the bb terminators
+        // When unwinding, frames without cleanup return to their caller with the panic flag set
+        let unwind_return_block = if self.panic_strategy == PanicStrategy::Unwind {
+            let mut statements = Vec::new();
+            self.emit_return(&mut statements);
+            unsafe {
+                let block = BinaryenBlock(self.func.module.module,
+                                          ptr::null(),
+                                          statements.as_ptr(),
+                                          BinaryenIndex(statements.len() as _));
+                Some(RelooperAddBlock(relooper, block))
+            }
+        } else {
+            None
+        };
+
+        // Create the relooper edges from the bb terminators. Failed asserts branch to a block
+        // reporting the panic, then trapping or starting to unwind.
         for (i, bb) in self.mir.basic_blocks().iter().enumerate() {
+            // the cleanup, or the caller, a panicking call continues to when unwinding
+            let unwind_target = |cleanup: Option<BasicBlock>| {
+                cleanup.map(|cleanup| relooper_blocks[cleanup.index()]).or(unwind_return_block)
+            };
+
+            self.span = bb.terminator().source_info.span;
             match bb.terminator().kind {
                 TerminatorKind::Goto { ref target } => {
                     debug!("emitting Branch for Goto, from bb{} to bb{}",
@@ -668,113 +1520,96 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                 TerminatorKind::Return => {
                     // handled during bb creation
                 }
-                TerminatorKind::Assert { ref target, .. } => {
-                    // TODO: An assert is not a GOTO!!!
-                    // Fix this!
-                    debug!("emitting Branch for Goto, from bb{} to bb{}",
+                TerminatorKind::Assert { ref cond, expected, ref msg, ref target, cleanup } => {
+                    debug!("emitting Branches for Assert, from bb{} to bb{} or a panic",
                            i,
                            target.index());
+
+                    let cond = self.trans_operand(cond);
                     unsafe {
+                        let cond = if expected {
+                            cond
+                        } else {
+                            BinaryenUnary(self.func.module.module, BinaryenEqZInt32(), cond)
+                        };
+                        let message = match *msg {
+                            AssertMessage::BoundsCheck { .. } => "index out of bounds",
+                            AssertMessage::Math(ref err) => err.description(),
+                        };
+                        let span = bb.terminator().source_info.span;
+                        let report = self.emit_static_panic_report(message, span);
+                        let statements = [report, self.emit_panic_end()];
+                        let panic_expr = BinaryenBlock(self.func.module.module,
+                                                       ptr::null(),
+                                                       statements.as_ptr(),
+                                                       BinaryenIndex(statements.len() as _));
+                        let panic_block = RelooperAddBlock(relooper, panic_expr);
+                        if self.panic_strategy == PanicStrategy::Unwind {
+                            RelooperAddBranch(panic_block,
+                                              unwind_target(cleanup).unwrap(),
+                                              BinaryenExpressionRef(ptr::null_mut()),
+                                              BinaryenExpressionRef(ptr::null_mut()));
+                        }
                         RelooperAddBranch(relooper_blocks[i],
                                           relooper_blocks[target.index()],
+                                          cond,
+                                          BinaryenExpressionRef(ptr::null_mut()));
+                        RelooperAddBranch(relooper_blocks[i],
+                                          panic_block,
                                           BinaryenExpressionRef(ptr::null_mut()),
                                           BinaryenExpressionRef(ptr::null_mut()));
                     }
                 }
-                TerminatorKind::Call { ref destination, ref cleanup, .. } => {
-                    let _ = cleanup; // FIXME
-                    match *destination {
-                        Some((_, ref target)) => {
-                            debug!("emitting Branch for Call, from bb{} to bb{}",
-                                   i,
-                                   target.index());
-                            unsafe {
-                                RelooperAddBranch(relooper_blocks[i],
-                                                  relooper_blocks[target.index()],
-                                                  BinaryenExpressionRef(ptr::null_mut()),
-                                                  BinaryenExpressionRef(ptr::null_mut()));
-                            }
+                TerminatorKind::Call { ref destination, cleanup, .. } => {
+                    let target = destination.as_ref().map(|&(_, target)| target);
+                    self.emit_call_edges(relooper_blocks[i],
+                                         target.map(|target| relooper_blocks[target.index()]),
+                                         unwind_target(cleanup),
+                                         calls_can_panic[i]);
+                }
+                TerminatorKind::Drop { target, unwind, .. } => {
+                    self.emit_call_edges(relooper_blocks[i],
+                                         Some(relooper_blocks[target.index()]),
+                                         unwind_target(unwind),
+                                         calls_can_panic[i]);
+                }
+                TerminatorKind::Resume => {
+                    if self.panic_strategy == PanicStrategy::Unwind {
+                        debug!("emitting Branch for Resume, from bb{} to the caller", i);
+                        unsafe {
+                            RelooperAddBranch(relooper_blocks[i],
+                                              unwind_return_block.unwrap(),
+                                              BinaryenExpressionRef(ptr::null_mut()),
+                                              BinaryenExpressionRef(ptr::null_mut()));
                         }
-                        _ => (),
                     }
                 }
-                _ => panic!("unimplemented terminator {:?}", bb.terminator().kind),
+                _ => {
+                    self.unsupported(UnsupportedKind::Terminator,
+                                     format!("terminator {:?}", bb.terminator().kind));
+                }
             }
         }
 
-        if !self.fun_types.contains_key(self.sig) {
-            let name = format!("rustfn-{}-{}", self.did.krate, self.did.index.as_u32());
-            let name = CString::new(name).expect("");
-            let name_ptr = name.as_ptr();
-            self.c_strings.push(name);
-            let name = &self.c_strings[self.c_strings.len() - 1];
-            let ty = self.func.create_sig_type(name, binaryen_ret);
-            self.fun_types.insert(self.sig.clone(), ty);
+        // Create the function prologue, unless the fn has no frame
+        unsafe {
+            let entry = match self.emit_prologue() {
+                Some(prologue) => {
+                    let prologue = RelooperAddBlock(relooper, prologue);
+                    if relooper_blocks.len() > 0 {
+                        RelooperAddBranch(prologue,
+                                          relooper_blocks[0],
+                                          BinaryenExpressionRef(ptr::null_mut()),
+                                          BinaryenExpressionRef(ptr::null_mut()));
+                    }
+                    prologue
+                }
+                None => relooper_blocks[0],
+            };
+
+            RelooperRenderAndDispose(relooper, entry, relooper_local, self.func.module.module)
         }
-
-        let nid = self.tcx.map.as_local_node_id(self.did).expect("");
-
-        unsafe {
-            if Some(self.did) == self.tcx.lang_items.panic_fn() {
-                // TODO: when it's possible to print characters or interact with the environment,
-                //       also handle #[lang = "panic_fmt"] to support panic messages
-                debug!("emitting Unreachable function for panic lang item");
-                // TODO(eholk): builderize this.
-                let var_types = self.func.binaryen_var_types();
-                BinaryenAddFunction(self.func.module.module,
-                                    fn_name_ptr,
-                                    *self.fun_types.get(self.sig).unwrap(),
-                                    var_types.as_ptr(),
-                                    var_types.len().into(),
-                                    BinaryenUnreachable(self.func.module.module));
-            } else {
-                // Create the function prologue
-                // TODO: the epilogue and prologue are not always necessary
-                debug!("emitting function prologue, SetLocal({}) + Load",
-                       (&stack_pointer_local).index());
-                let copy_sp = BinaryenSetLocal(self.func.module.module,
-                                               stack_pointer_local.into(),
-                                               self.emit_read_sp());
-                let prologue = RelooperAddBlock(relooper, copy_sp);
-
-                if relooper_blocks.len() > 0 {
-                    RelooperAddBranch(prologue,
-                                      relooper_blocks[0],
-                                      BinaryenExpressionRef(ptr::null_mut()),
-                                      BinaryenExpressionRef(ptr::null_mut()));
-                }
-
-                let body = RelooperRenderAndDispose(relooper,
-                                                    prologue,
-                                                    relooper_local.into(),
-                                                    self.func.module.module);
-
-                // TODO(eholk): builderize this.
-                let var_types = self.func.binaryen_var_types();
-                BinaryenAddFunction(self.func.module.module,
-                                    fn_name_ptr,
-                                    *self.fun_types.get(self.sig).unwrap(),
-                                    var_types.as_ptr(),
-                                    var_types.len().into(),
-                                    body);
-
-                // TODO: don't unconditionally export this
-                BinaryenAddExport(self.func.module.module, fn_name_ptr, fn_name_ptr);
-            }
-
-            if self.entry_fn == Some(nid) {
-                let is_start = self.mir.arg_decls.len() == 2;
-                let entry_fn_name = if is_start { "start" } else { "main" };
-                let wasm_start = self.generate_runtime_start(&entry_fn_name);
-                debug!("emitting wasm Start fn into entry_fn {:?}",
-                       self.tcx.item_path_str(self.did));
-                BinaryenSetStart(self.func.module.module, wasm_start);
-            }
-        }
-
-        debug!("done translating fn {:?}\n",
-               self.tcx.item_path_str(self.did));
-    }
+    }
 
     fn trans_assignment(&mut self,
                         lvalue: &Lvalue<'tcx>,
@@ -808,6 +1643,7 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                             let ptr = BinaryenGetLocal(self.func.module.module,
                                                        dest.index,
                                                        rust_ty_to_binaryen(dest_ty));
+                            let ptr = self.emit_checked_ptr(ptr);
                             // TODO: match on the dest_ty to know how many bytes to write, not just
                             // i32s
                             BinaryenStore(self.func.module.module,
@@ -831,16 +1667,14 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
             }
 
             Rvalue::UnaryOp(ref op, ref operand) => {
+                let operand_ty = operand.ty(self.mir, *self.tcx);
                 let operand = self.trans_operand(operand);
-                unsafe {
-                    let op = match *op {
-                        UnOp::Not => BinaryenEqZInt32(),
-                        _ => panic!("unimplemented UnOp: {:?}", op),
-                    };
-                    let op = BinaryenUnary(self.func.module.module, op, operand);
-                    let statement = BinaryenSetLocal(self.func.module.module, dest.index, op);
-                    statements.push(statement);
-                }
+                let value = self.emit_unary_op(*op, operand, operand_ty);
+                debug!("emitting {:?} for Assign UnaryOp '{:?} = {:?}'",
+                       dest,
+                       lvalue,
+                       rvalue);
+                statements.push(self.emit_assign(dest, dest_ty, value));
             }
 
             Rvalue::BinaryOp(ref op, ref left, ref right) => {
@@ -848,25 +1682,12 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                 let right = self.trans_operand(right);
 
                 unsafe {
-                    // TODO: match on dest_ty.sty to implement binary ops for other types than just
-                    // i32s
-                    // TODO: check if the dest_layout is signed or not (CEnum, etc)
-                    // TODO: comparisons are signed only for now, so implement unsigned ones
-                    let op = match *op {
-                        BinOp::Add => BinaryenAddInt32(),
-                        BinOp::Sub => BinaryenSubInt32(),
-                        BinOp::Mul => BinaryenMulInt32(),
-                        BinOp::Div => BinaryenDivSInt32(),
-                        BinOp::BitAnd => BinaryenAndInt32(),
-                        BinOp::BitOr => BinaryenOrInt32(),
-                        BinOp::BitXor => BinaryenXorInt32(),
-                        BinOp::Eq => BinaryenEqInt32(),
-                        BinOp::Ne => BinaryenNeInt32(),
-                        BinOp::Lt => BinaryenLtSInt32(),
-                        BinOp::Le => BinaryenLeSInt32(),
-                        BinOp::Gt => BinaryenGtSInt32(),
-                        BinOp::Ge => BinaryenGeSInt32(),
-                        _ => panic!("unimplemented BinOp: {:?}", op),
+                    let op = match binary_op(*op) {
+                        Some(op) => op,
+                        None => {
+                            self.unsupported(UnsupportedKind::Rvalue, format!("BinOp {:?}", op));
+                            return;
+                        }
                     };
 
                     let op = BinaryenBinary(self.func.module.module, op, left, right);
@@ -880,6 +1701,7 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                             let ptr = BinaryenGetLocal(self.func.module.module,
                                                        dest.index,
                                                        rust_ty_to_binaryen(dest_ty));
+                            let ptr = self.emit_checked_ptr(ptr);
                             // TODO: match on the dest_ty to know how many bytes to write, not just
                             // i32s
                             BinaryenStore(self.func.module.module,
@@ -903,121 +1725,23 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
             }
 
             Rvalue::CheckedBinaryOp(ref op, ref left, ref right) => {
-                // TODO: This shouldn't just be a copy BinaryOp above!
-                // We should do some actual _checking_!
-
-                let left = self.trans_operand(left);
-                let right = self.trans_operand(right);
-
-                unsafe {
-                    // TODO: match on dest_ty.sty to implement binary ops for other types than just
-                    // i32s
-                    // TODO: check if the dest_layout is signed or not (CEnum, etc)
-                    // TODO: operations are signed only for now, so implement unsigned ones
-                    let op = match *op {
-                        BinOp::Add => BinaryenAddInt64(),
-                        BinOp::Sub => BinaryenSubInt64(),
-                        BinOp::Mul => BinaryenMulInt64(),
-                        BinOp::Div => BinaryenDivSInt64(),
-                        _ => panic!("unimplemented BinOp: {:?}", op),
-                    };
-
-                    let op = BinaryenBinary(self.func.module.module,
-                                            op,
-                                            BinaryenUnary(self.func.module.module,
-                                                          BinaryenExtendSInt32(),
-                                                          left),
-                                            BinaryenUnary(self.func.module.module,
-                                                          BinaryenExtendSInt32(),
-                                                          right));
-
-                    let checked_local = self.checked_op_local.unwrap();
-
-                    statements.push(BinaryenSetLocal(self.func.module.module, checked_local, op));
-
-                    let lower = BinaryenUnary(self.func.module.module,
-                                              BinaryenWrapInt64(),
-                                              BinaryenGetLocal(self.func.module.module,
-                                                               checked_local,
-                                                               BinaryenInt64()));
-
-                    let thirty_two = BinaryenConst(self.func.module.module,
-                                                   BinaryenLiteralInt64(32));
-
-                    let upper =
-                        BinaryenUnary(self.func.module.module,
-                                      BinaryenWrapInt64(),
-                                      BinaryenBinary(self.func.module.module,
-                                                     BinaryenShrUInt64(),
-                                                     BinaryenGetLocal(self.func.module.module,
-                                                                      self.checked_op_local
-                                                                          .unwrap(),
-                                                                      BinaryenInt64()),
-                                                     thirty_two));
-
-                    match dest.offset {
-                        Some(offset) => {
-                            debug!("emitting Store + GetLocal({}) for Assign Checked BinaryOp \
-                                    '{:?} = {:?}'",
-                                   dest.index.0,
-                                   lvalue,
-                                   rvalue);
-                            let ptr = BinaryenGetLocal(self.func.module.module,
-                                                       dest.index,
-                                                       rust_ty_to_binaryen(dest_ty));
-                            // TODO: match on the dest_ty to know how many bytes to write, not just
-                            // i32s
-                            statements.push(BinaryenStore(self.func.module.module,
-                                                          4,
-                                                          offset,
-                                                          0,
-                                                          ptr,
-                                                          lower,
-                                                          BinaryenInt32()));
-                            statements.push(BinaryenStore(self.func.module.module,
-                                                          4,
-                                                          offset + 4,
-                                                          0,
-                                                          ptr,
-                                                          upper,
-                                                          BinaryenInt32()));
-                        }
-                        None => {
-                            let dest_size = self.type_size(dest_ty) as i32 * 8;
-                            // NOTE: use the variant's min_size and alignment for dest_size ?
-                            debug!("allocating tuple in linear memory to SetLocal({}), size: \
-                                    {:?} bytes",
-                                   dest.index.0,
-                                   dest_size);
-                            let allocation = self.emit_alloca(dest.index, dest_size);
-                            statements.push(allocation);
-                            let ptr = BinaryenGetLocal(self.func.module.module,
-                                                       dest.index,
-                                                       rust_ty_to_binaryen(dest_ty));
-
-                            statements.push(BinaryenStore(self.func.module.module,
-                                                          4,
-                                                          0,
-                                                          0,
-                                                          ptr,
-                                                          lower,
-                                                          BinaryenInt32()));
-                            statements.push(BinaryenStore(self.func.module.module,
-                                                          4,
-                                                          4,
-                                                          0,
-                                                          ptr,
-                                                          upper,
-                                                          BinaryenInt32()));
-                        }
-                    }
-                }
+                debug!("emitting checked BinaryOp for Assign '{:?} = {:?}'", lvalue, rvalue);
+                self.trans_checked_binary_op(*op, left, right, dest, dest_ty, statements);
             }
 
             Rvalue::Ref(_, _, ref lvalue) => {
                 // TODO: for shared refs only ?
                 // TODO: works for refs to "our stack", but not the locals on the wasm stack yet
-                let expr = self.trans_operand(&Operand::Consume(lvalue.clone()));
+                let lvalue_ty = lvalue.ty(self.mir, *self.tcx).to_ty(*self.tcx);
+                let expr = if self.is_zst(lvalue_ty) {
+                    // like rustc, point zero-sized referents to a dangling, aligned address
+                    let align = self.type_layout(lvalue_ty).align(&self.tcx.data_layout).abi();
+                    unsafe {
+                        BinaryenConst(self.func.module.module, BinaryenLiteralInt32(align as i32))
+                    }
+                } else {
+                    self.trans_operand(&Operand::Consume(lvalue.clone()))
+                };
                 unsafe {
                     debug!("emitting SetLocal({}) for Assign Ref '{:?} = {:?}'",
                            dest.index.0,
@@ -1029,22 +1753,22 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
             }
 
             Rvalue::Aggregate(ref kind, ref operands) => {
+                if !aggregate_supported(kind) {
+                    self.unsupported(UnsupportedKind::Rvalue,
+                                     format!("Assign Aggregate {:?}", kind));
+                    return;
+                }
                 match *kind {
                     AggregateKind::Adt(ref adt_def, _, ref substs, _) => {
                         let dest_layout = self.type_layout_with_substs(dest_ty, substs);
 
-                        // TODO: check sizes, alignments (abi vs preferred), etc
-                        let dest_size = self.type_size_with_substs(dest_ty, substs) as i32 * 8;
-
                         match *dest_layout {
                             Layout::Univariant { ref variant, .. } => {
-                                // NOTE: use the variant's min_size and alignment for dest_size ?
                                 debug!("allocating struct '{:?}' in linear memory to \
-                                        SetLocal({}), size: {:?} bytes ",
+                                        SetLocal({})",
                                        adt_def,
-                                       dest.index.0,
-                                       dest_size);
-                                let allocation = self.emit_alloca(dest.index, dest_size);
+                                       dest.index.0);
+                                let allocation = self.emit_frame_slot(dest.index);
                                 statements.push(allocation);
 
                                 let offsets = ::std::iter::once(0)
@@ -1052,7 +1776,7 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                 debug!("emitting Stores for struct '{:?}' fields, values: {:?}",
                                        adt_def,
                                        operands);
-                                self.emit_assign_fields(offsets, operands, statements);
+                                self.emit_assign_fields(dest.index, offsets, operands, statements);
                             }
 
                             Layout::General { discr, ref variants, .. } => {
@@ -1062,11 +1786,10 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                     let discr_size = discr.size().bytes() as u32;
 
                                     debug!("allocating Enum '{:?}' in linear memory to \
-                                            SetLocal({}), size: {:?} bytes",
+                                            SetLocal({})",
                                            adt_def,
-                                           dest.index.0,
-                                           dest_size);
-                                    let allocation = self.emit_alloca(dest.index, dest_size);
+                                           dest.index.0);
+                                    let allocation = self.emit_frame_slot(dest.index);
                                     statements.push(allocation);
 
                                     // set enum discr
@@ -1077,11 +1800,15 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                         let discr_val =
                                             BinaryenConst(self.func.module.module,
                                                           BinaryenLiteralInt32(discr_val as i32));
+                                        let slot = BinaryenGetLocal(self.func.module.module,
+                                                                    dest.index,
+                                                                    BinaryenInt32());
+                                        let slot = self.emit_checked_ptr(slot);
                                         let write_discr = BinaryenStore(self.func.module.module,
                                                                         discr_size,
                                                                         0,
                                                                         0,
-                                                                        self.emit_read_sp(),
+                                                                        slot,
                                                                         discr_val,
                                                                         BinaryenInt32());
                                         statements.push(write_discr);
@@ -1095,7 +1822,10 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                         .offset_after_field
                                         .iter()
                                         .map(|s| s.bytes());
-                                    self.emit_assign_fields(offsets, operands, statements);
+                                    self.emit_assign_fields(dest.index,
+                                                            offsets,
+                                                            operands,
+                                                            statements);
                                 } else {
                                     panic!("tried to assign {:?} to Layout::General", kind);
                                 }
@@ -1106,7 +1836,10 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                 if let AggregateKind::Adt(adt_def, variant, _, _) = *kind {
                                     let discr_size = discr.size().bytes();
                                     if discr_size > 4 {
-                                        panic!("unimplemented >32bit discr size: {}", discr_size);
+                                        self.unsupported(UnsupportedKind::Layout,
+                                                         format!(">32bit discr size: {}",
+                                                                 discr_size));
+                                        return;
                                     }
 
                                     // TODO: handle signed vs unsigned here as well, or just in the
@@ -1136,9 +1869,10 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                             }
 
                             _ => {
-                                panic!("unimplemented Assign Aggregate Adt {:?} on Layout {:?}",
-                                       adt_def,
-                                       dest_layout)
+                                let message = format!("Assign Aggregate Adt {:?} on Layout {:?}",
+                                                      adt_def,
+                                                      dest_layout);
+                                self.unsupported(UnsupportedKind::Layout, message);
                             }
                         }
                     }
@@ -1151,14 +1885,9 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                         } else {
                             match *dest_layout {
                                 Layout::Univariant { ref variant, .. } => {
-                                    let dest_size = self.type_size(dest_ty) as i32 * 8;
-                                    // NOTE: use the variant's min_size and alignment for
-                                    // dest_size ?
-                                    debug!("allocating tuple in linear memory to SetLocal({}), \
-                                            size: {:?} bytes",
-                                           dest.index.0,
-                                           dest_size);
-                                    let allocation = self.emit_alloca(dest.index, dest_size);
+                                    debug!("allocating tuple in linear memory to SetLocal({})",
+                                           dest.index.0);
+                                    let allocation = self.emit_frame_slot(dest.index);
                                     statements.push(allocation);
 
                                     let offsets = ::std::iter::once(0)
@@ -1167,241 +1896,1179 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                             .map(|s| s.bytes()));
                                     debug!("emitting Stores for tuple fields, values: {:?}",
                                            operands);
-                                    self.emit_assign_fields(offsets, operands, statements);
+                                    self.emit_assign_fields(dest.index,
+                                                            offsets,
+                                                            operands,
+                                                            statements);
                                 }
                                 _ => {
-                                    panic!("unimplemented Tuple Assign '{:?} = {:?}'",
-                                           lvalue,
-                                           rvalue)
+                                    let message = format!("Tuple Assign '{:?} = {:?}'",
+                                                          lvalue,
+                                                          rvalue);
+                                    self.unsupported(UnsupportedKind::Layout, message);
                                 }
                             }
                         }
                     }
 
-                    _ => panic!("unimplemented Assign Aggregate {:?}", kind),
+                    AggregateKind::Vec => {
+                        let elem_ty = match dest_ty.sty {
+                            ty::TyArray(elem_ty, _) => elem_ty,
+                            _ => {
+                                let message = format!("Array Assign '{:?} = {:?}'", lvalue, rvalue);
+                                self.unsupported(UnsupportedKind::Layout, message);
+                                return;
+                            }
+                        };
+                        debug!("allocating array in linear memory to SetLocal({})",
+                               dest.index.0);
+                        let allocation = self.emit_frame_slot(dest.index);
+                        statements.push(allocation);
+
+                        let elem_size = self.type_size(elem_ty) as u64;
+                        let offsets = (0..operands.len() as u64).map(|i| i * elem_size);
+                        debug!("emitting Stores for array elements, values: {:?}", operands);
+                        self.emit_assign_fields(dest.index, offsets, operands, statements);
+                    }
+
+                    AggregateKind::Closure(..) => {
+                        bug!("unsupported Aggregate {:?} translated", kind);
+                    }
                 }
             }
 
             Rvalue::Cast(ref kind, ref operand, _) => {
-                if dest.offset.is_some() {
-                    panic!("unimplemented '{:?}' Cast with offset", kind);
-                }
+                let src_ty = operand.ty(self.mir, *self.tcx);
+                debug!("emitting '{:?}' Cast from {:?} to {:?} for Assign '{:?} = {:?}'",
+                       kind,
+                       src_ty,
+                       dest_ty,
+                       lvalue,
+                       rvalue);
 
                 match *kind {
                     CastKind::Misc => {
                         let src = self.trans_operand(operand);
-                        let src_ty = operand.ty(self.mir, *self.tcx);
-                        let src_layout = self.type_layout(src_ty);
-
-                        // TODO: handle more of the casts (miri doesn't really handle every Misc
-                        // cast either right now)
-                        match (src_layout, &dest_ty.sty) {
-                            (&Layout::Scalar { .. }, &ty::TyInt(_)) |
-                            (&Layout::Scalar { .. }, &ty::TyUint(_)) => unsafe {
-                                debug!("emitting SetLocal({}) for Scalar Cast Assign '{:?} = \
-                                        {:?}'",
-                                       dest.index.0,
-                                       lvalue,
-                                       rvalue);
-                                let copy_value =
-                                    BinaryenSetLocal(self.func.module.module, dest.index, src);
-                                statements.push(copy_value);
-                            },
-                            (&Layout::CEnum { .. }, &ty::TyInt(_)) |
-                            (&Layout::CEnum { .. }, &ty::TyUint(_)) => unsafe {
-                                debug!("emitting SetLocal({}) for CEnum Cast Assign '{:?} = {:?}'",
-                                       dest.index.0,
-                                       lvalue,
-                                       rvalue);
-                                let copy_discr =
-                                    BinaryenSetLocal(self.func.module.module, dest.index, src);
-                                statements.push(copy_discr);
-                            },
+                        let value = self.emit_misc_cast(src, src_ty, dest_ty);
+                        statements.push(self.emit_assign(dest, dest_ty, value));
+                    }
+                    CastKind::ReifyFnPointer => {
+                        // fn pointers are indices into the wasm function table
+                        let fn_key = match *operand {
+                            Operand::Constant(Constant {
+                                literal: Literal::Item { def_id, substs }, ..
+                            }) => self.trans_fn_instance(def_id, substs),
                             _ => {
-                                panic!("unimplemented '{:?}' Cast '{:?} = {:?}', for {:?} to {:?}",
-                                       kind,
-                                       lvalue,
-                                       rvalue,
-                                       src_layout,
-                                       dest_ty.sty)
+                                self.unsupported(UnsupportedKind::Cast,
+                                                 format!("ReifyFnPointer of {:?}", operand));
+                                return;
                             }
-                        }
+                        };
+                        // a failed trait selection has already been reported
+                        let fn_key = match fn_key {
+                            Some(fn_key) => fn_key,
+                            None => return,
+                        };
+                        let index = self.fn_table_index(fn_key) as i32;
+                        let value = unsafe {
+                            BinaryenConst(self.func.module.module, BinaryenLiteralInt32(index))
+                        };
+                        statements.push(self.emit_assign(dest, dest_ty, value));
                     }
-                    _ => {
-                        panic!("unimplemented '{:?}' Cast '{:?} = {:?}'",
-                               kind,
-                               lvalue,
-                               rvalue)
+                    CastKind::UnsafeFnPointer => {
+                        let value = self.trans_operand(operand);
+                        statements.push(self.emit_assign(dest, dest_ty, value));
+                    }
+                    CastKind::Unsize => {
+                        self.trans_unsize(operand, src_ty, dest, dest_ty, statements);
                     }
                 }
             }
 
-            _ => panic!("unimplemented Assign '{:?} = {:?}'", lvalue, rvalue),
+            _ => {
+                self.unsupported(UnsupportedKind::Rvalue,
+                                 format!("Assign '{:?} = {:?}'", lvalue, rvalue));
+            }
         }
     }
 
-    // TODO: handle > 2GB allocations, when more types are handled and there's a consistent story
-    // around signed and unsigned
-    fn emit_alloca(&self, dest: BinaryenIndex, dest_size: i32) -> BinaryenExpressionRef {
+    /// `!` on bools and integers, and `-` on signed integers and floats. Integers narrower than
+    /// 32 bits are kept extended in their i32.
+    fn emit_unary_op(&mut self,
+                     op: UnOp,
+                     operand: BinaryenExpressionRef,
+                     ty: Ty<'tcx>)
+                     -> BinaryenExpressionRef {
+        let module = self.func.module.module;
+        let repr = match unary_op_repr(op, ty, self.type_layout(ty)) {
+            Some(repr) => repr,
+            None => {
+                return self.unsupported(UnsupportedKind::Rvalue,
+                                        format!("UnOp {:?} on {:?}", op, ty));
+            }
+        };
         unsafe {
-            let sp = self.emit_sp();
-            let read_sp = BinaryenLoad(self.func.module.module, 4, 0, 0, 0, BinaryenInt32(), sp);
-
-            let dest_size = BinaryenLiteralInt32(dest_size);
-            let dest_size = BinaryenConst(self.func.module.module, dest_size);
-            let decr_sp = BinaryenBinary(self.func.module.module,
-                                         BinaryenSubInt32(),
-                                         read_sp,
-                                         dest_size);
-            let write_local = BinaryenTeeLocal(self.func.module.module, dest, decr_sp);
-            let write_sp = BinaryenStore(self.func.module.module,
-                                         4,
-                                         0,
-                                         0,
-                                         sp,
-                                         write_local,
-                                         BinaryenInt32());
-            write_sp
+            let (bits, signed) = match repr {
+                UnaryRepr::Float(float_bits) => {
+                    let neg = if float_bits == 64 {
+                        BinaryenNegFloat64()
+                    } else {
+                        BinaryenNegFloat32()
+                    };
+                    return BinaryenUnary(module, neg, operand);
+                }
+                UnaryRepr::Bool => return BinaryenUnary(module, BinaryenEqZInt32(), operand),
+                UnaryRepr::Int(bits, signed) => (bits, signed),
+            };
+            let wide = bits == 64;
+            let (value, normalize) = match op {
+                UnOp::Not => {
+                    let (xor, ones) = if wide {
+                        (BinaryenXorInt64(), BinaryenConst(module, BinaryenLiteralInt64(-1)))
+                    } else {
+                        (BinaryenXorInt32(), BinaryenConst(module, BinaryenLiteralInt32(-1)))
+                    };
+                    // flipping the bits of a sign-extended value keeps it sign-extended
+                    (BinaryenBinary(module, xor, operand, ones), !signed)
+                }
+                UnOp::Neg => {
+                    let (sub, zero) = if wide {
+                        (BinaryenSubInt64(), BinaryenConst(module, BinaryenLiteralInt64(0)))
+                    } else {
+                        (BinaryenSubInt32(), BinaryenConst(module, BinaryenLiteralInt32(0)))
+                    };
+                    // -MIN wraps around to MIN
+                    (BinaryenBinary(module, sub, zero, operand), true)
+                }
+            };
+
+            if wide || !normalize {
+                value
+            } else {
+                self.emit_int_cast(value, (32, signed), bits, signed)
+            }
         }
     }
 
-    fn emit_sp(&self) -> BinaryenExpressionRef {
+    /// Computes `op` and whether it overflowed, into the `(T, bool)` tuple at `dest`.
+    fn trans_checked_binary_op(&mut self,
+                               op: BinOp,
+                               left: &Operand<'tcx>,
+                               right: &Operand<'tcx>,
+                               dest: BinaryenLvalue,
+                               dest_ty: Ty<'tcx>,
+                               statements: &mut Vec<BinaryenExpressionRef>) {
+        let operand_ty = left.ty(self.mir, *self.tcx);
+        let (bits, signed) = match self.int_repr(operand_ty) {
+            Some(repr) if checked_binary_op_supported(op) => repr,
+            _ => {
+                self.unsupported(UnsupportedKind::Rvalue,
+                                 format!("checked BinaryOp {:?} on {:?}", op, operand_ty));
+                return;
+            }
+        };
+        let shift_ty = right.ty(self.mir, *self.tcx);
+        let left = self.trans_operand(left);
+        let right = self.trans_operand(right);
+
+        let (result, overflow) = match op {
+            BinOp::Shl | BinOp::Shr => {
+                let shift_bits = match self.int_repr(shift_ty) {
+                    Some((shift_bits, _)) => shift_bits,
+                    None => {
+                        self.unsupported(UnsupportedKind::Rvalue,
+                                         format!("shift amount of type {:?}", shift_ty));
+                        return;
+                    }
+                };
+                self.emit_checked_shift(op, left, right, (bits, signed), shift_bits, statements)
+            }
+            BinOp::Add | BinOp::Sub | BinOp::Mul if bits == 64 => {
+                self.emit_checked_op64(op, left, right, signed, statements)
+            }
+            // Add, Sub and Mul
+            _ => self.emit_checked_op32(op, left, right, (bits, signed), statements),
+        };
+
+        let flag_offset = match *self.type_layout(dest_ty) {
+            Layout::Univariant { ref variant, .. } => variant.field_offset(1).bytes() as u32,
+            ref layout => {
+                self.unsupported(UnsupportedKind::Layout,
+                                 format!("checked BinaryOp into Layout {:?}", layout));
+                return;
+            }
+        };
+
+        let offset = match dest.offset {
+            Some(offset) => offset,
+            None => {
+                debug!("allocating tuple in linear memory to SetLocal({})", dest.index.0);
+                statements.push(self.emit_frame_slot(dest.index));
+                0
+            }
+        };
+
+        debug!("emitting Stores to GetLocal({}) of the checked BinaryOp result and overflow flag",
+               dest.index.0);
         unsafe {
-            BinaryenConst(self.func.module.module,
-                          BinaryenLiteralInt32(STACK_POINTER_ADDRESS))
+            let module = self.func.module.module;
+            let ptr = BinaryenGetLocal(module, dest.index, BinaryenInt32());
+            statements.push(self.emit_store(operand_ty, ptr, offset, result));
+            let ptr = self.emit_checked_ptr(BinaryenGetLocal(module, dest.index, BinaryenInt32()));
+            statements.push(BinaryenStore(module,
+                                          1,
+                                          offset + flag_offset,
+                                          0,
+                                          ptr,
+                                          overflow,
+                                          BinaryenInt32()));
         }
     }
 
-    fn emit_read_sp(&self) -> BinaryenExpressionRef {
+    /// Add, sub and mul of integers up to 32 bits can't overflow an i64: the operation overflowed
+    /// when the wide result differs from the wrapped one, extended back.
+    fn emit_checked_op32(&mut self,
+                         op: BinOp,
+                         left: BinaryenExpressionRef,
+                         right: BinaryenExpressionRef,
+                         (bits, signed): (u32, bool),
+                         statements: &mut Vec<BinaryenExpressionRef>)
+                         -> (BinaryenExpressionRef, BinaryenExpressionRef) {
+        let module = self.func.module.module;
+        let checked_local = self.checked_op_local.unwrap();
         unsafe {
-            BinaryenLoad(self.func.module.module,
-                         4,
-                         0,
-                         0,
-                         0,
-                         BinaryenInt32(),
-                         self.emit_sp())
+            let extend = if signed {
+                BinaryenExtendSInt32()
+            } else {
+                BinaryenExtentUInt32()
+            };
+            let wide_op = match op {
+                BinOp::Add => BinaryenAddInt64(),
+                BinOp::Sub => BinaryenSubInt64(),
+                _ => BinaryenMulInt64(),
+            };
+            let wide = BinaryenBinary(module,
+                                      wide_op,
+                                      BinaryenUnary(module, extend, left),
+                                      BinaryenUnary(module, extend, right));
+            statements.push(BinaryenSetLocal(module, checked_local, wide));
+
+            let get_wide = || BinaryenGetLocal(module, checked_local, BinaryenInt64());
+            let wrapped = self.emit_int_cast(get_wide(), (64, signed), bits, signed);
+            let wrapped_local: BinaryenIndex =
+                self.func.create_local(builder::ReprType::Int32).index().into();
+            statements.push(BinaryenSetLocal(module, wrapped_local, wrapped));
+
+            let get_wrapped = || BinaryenGetLocal(module, wrapped_local, BinaryenInt32());
+            let overflow = BinaryenBinary(module,
+                                          BinaryenNeInt64(),
+                                          get_wide(),
+                                          BinaryenUnary(module, extend, get_wrapped()));
+            (get_wrapped(), overflow)
         }
     }
 
-    fn emit_assign_fields<I>(&mut self,
-                             offsets: I,
-                             operands: &[Operand<'tcx>],
-                             statements: &mut Vec<BinaryenExpressionRef>)
-        where I: IntoIterator<Item = u64>
-    {
+    /// 64-bit add, sub and mul check the wrapped result against the operands.
+    fn emit_checked_op64(&mut self,
+                         op: BinOp,
+                         left: BinaryenExpressionRef,
+                         right: BinaryenExpressionRef,
+                         signed: bool,
+                         statements: &mut Vec<BinaryenExpressionRef>)
+                         -> (BinaryenExpressionRef, BinaryenExpressionRef) {
+        let module = self.func.module.module;
+        let a: BinaryenIndex = self.func.create_local(builder::ReprType::Int64).index().into();
+        let b: BinaryenIndex = self.func.create_local(builder::ReprType::Int64).index().into();
+        let r: BinaryenIndex = self.func.create_local(builder::ReprType::Int64).index().into();
         unsafe {
-            let read_sp = self.emit_read_sp();
+            let get = |index| BinaryenGetLocal(module, index, BinaryenInt64());
+            let int64 = |value| BinaryenConst(module, BinaryenLiteralInt64(value));
+            let wide_op = match op {
+                BinOp::Add => BinaryenAddInt64(),
+                BinOp::Sub => BinaryenSubInt64(),
+                _ => BinaryenMulInt64(),
+            };
+            statements.push(BinaryenSetLocal(module, a, left));
+            statements.push(BinaryenSetLocal(module, b, right));
+            let wide = BinaryenBinary(module, wide_op, get(a), get(b));
+            statements.push(BinaryenSetLocal(module, r, wide));
+
+            let overflow = match (op, signed) {
+                (BinOp::Add, true) => {
+                    // the operands have the same sign, and the result's is different
+                    let a_r = BinaryenBinary(module, BinaryenXorInt64(), get(a), get(r));
+                    let b_r = BinaryenBinary(module, BinaryenXorInt64(), get(b), get(r));
+                    let sign = BinaryenBinary(module, BinaryenAndInt64(), a_r, b_r);
+                    BinaryenBinary(module, BinaryenLtSInt64(), sign, int64(0))
+                }
+                (BinOp::Add, false) => BinaryenBinary(module, BinaryenLtUInt64(), get(r), get(a)),
+                (BinOp::Sub, true) => {
+                    // the operands have different signs, and the result's is not the left one's
+                    let a_b = BinaryenBinary(module, BinaryenXorInt64(), get(a), get(b));
+                    let a_r = BinaryenBinary(module, BinaryenXorInt64(), get(a), get(r));
+                    let sign = BinaryenBinary(module, BinaryenAndInt64(), a_b, a_r);
+                    BinaryenBinary(module, BinaryenLtSInt64(), sign, int64(0))
+                }
+                (BinOp::Sub, false) => BinaryenBinary(module, BinaryenLtUInt64(), get(a), get(b)),
+                (_, signed) => {
+                    // a * b overflowed when a != 0 and r / a != b. The division would trap for
+                    // MIN / -1, the only other overflow, so -1 is checked separately.
+                    let zero = BinaryenConst(module, BinaryenLiteralInt32(0));
+                    let div = if signed {
+                        BinaryenDivSInt64()
+                    } else {
+                        BinaryenDivUInt64()
+                    };
+                    let quotient = BinaryenBinary(module, div, get(r), get(a));
+                    let mut checked = BinaryenBinary(module, BinaryenNeInt64(), quotient, get(b));
+                    if signed {
+                        let minus_one =
+                            BinaryenBinary(module, BinaryenEqInt64(), get(a), int64(-1));
+                        let min = BinaryenBinary(module,
+                                                 BinaryenEqInt64(),
+                                                 get(b),
+                                                 int64(i64::min_value()));
+                        checked = BinaryenIf(module, minus_one, min, checked);
+                    }
+                    let a_is_zero = BinaryenUnary(module, BinaryenEqZInt64(), get(a));
+                    BinaryenIf(module, a_is_zero, zero, checked)
+                }
+            };
+            (get(r), overflow)
+        }
+    }
 
-            for (offset, operand) in offsets.into_iter().zip(operands) {
-                // let operand_ty = self.mir.operand_ty(*self.tcx, operand);
-                // TODO: match on the operand_ty to know how many bytes to store, not just i32s
-                let src = self.trans_operand(operand);
-                let write_field = BinaryenStore(self.func.module.module,
-                                                4,
-                                                offset as u32,
-                                                0,
-                                                read_sp,
-                                                src,
-                                                BinaryenInt32());
-                statements.push(write_field);
-            }
+    /// Shifts overflow when the amount is not lower than the bit width, and are then done by the
+    /// amount masked to the bit width, like the unchecked ones.
+    fn emit_checked_shift(&mut self,
+                          op: BinOp,
+                          left: BinaryenExpressionRef,
+                          right: BinaryenExpressionRef,
+                          (bits, signed): (u32, bool),
+                          shift_bits: u32,
+                          statements: &mut Vec<BinaryenExpressionRef>)
+                          -> (BinaryenExpressionRef, BinaryenExpressionRef) {
+        let module = self.func.module.module;
+        let wide_amount = shift_bits > 32;
+        let (amount_repr, amount_ty) = if wide_amount {
+            (builder::ReprType::Int64, BinaryenInt64())
+        } else {
+            (builder::ReprType::Int32, BinaryenInt32())
+        };
+        let amount_local: BinaryenIndex = self.func.create_local(amount_repr).index().into();
+        unsafe {
+            statements.push(BinaryenSetLocal(module, amount_local, right));
+            let get_amount = || BinaryenGetLocal(module, amount_local, amount_ty);
+
+            // narrow amounts are extended in their i32, so negative ones are large unsigned values
+            let (overflow, amount) = if wide_amount {
+                let limit = BinaryenConst(module, BinaryenLiteralInt64(bits as i64));
+                (BinaryenBinary(module, BinaryenGeUInt64(), get_amount(), limit),
+                 BinaryenUnary(module, BinaryenWrapInt64(), get_amount()))
+            } else {
+                let limit = BinaryenConst(module, BinaryenLiteralInt32(bits as i32));
+                (BinaryenBinary(module, BinaryenGeUInt32(), get_amount(), limit), get_amount())
+            };
+            let mask = BinaryenConst(module, BinaryenLiteralInt32(bits as i32 - 1));
+            let amount = BinaryenBinary(module, BinaryenAndInt32(), amount, mask);
+
+            let result = if bits == 64 {
+                let shift_op = match (op, signed) {
+                    (BinOp::Shl, _) => BinaryenShlInt64(),
+                    (_, true) => BinaryenShrSInt64(),
+                    (_, false) => BinaryenShrUInt64(),
+                };
+                let amount = BinaryenUnary(module, BinaryenExtentUInt32(), amount);
+                BinaryenBinary(module, shift_op, left, amount)
+            } else {
+                // right shifts keep narrow values extended, left shifts need to be normalized
+                match (op, signed) {
+                    (BinOp::Shl, _) => {
+                        let shifted = BinaryenBinary(module, BinaryenShlInt32(), left, amount);
+                        self.emit_int_cast(shifted, (32, signed), bits, signed)
+                    }
+                    (_, true) => BinaryenBinary(module, BinaryenShrSInt32(), left, amount),
+                    (_, false) => BinaryenBinary(module, BinaryenShrUInt32(), left, amount),
+                }
+            };
+            (result, overflow)
         }
     }
 
-    fn trans_lval(&mut self, lvalue: &Lvalue<'tcx>) -> Option<BinaryenLvalue> {
-        let i = match *lvalue {
-            Lvalue::Arg(i) => i.index() as u32,
-            Lvalue::Var(i) => {
-                match self.var_map[i.index()] {
-                    Some(i) => i as u32,
-                    None => return None,
+    /// Lowers an `as` cast between scalars: integers, floats, C-like enums, bools, chars and
+    /// pointers.
+    fn emit_misc_cast(&mut self,
+                      value: BinaryenExpressionRef,
+                      src_ty: Ty<'tcx>,
+                      dest_ty: Ty<'tcx>)
+                      -> BinaryenExpressionRef {
+        let module = self.func.module.module;
+        let cast = misc_cast(src_ty, self.type_layout(src_ty), dest_ty, self.type_layout(dest_ty));
+        match cast {
+            Some(MiscCast::Float(src_bits, dest_bits)) => unsafe {
+                if src_bits == dest_bits {
+                    value
+                } else if src_bits < dest_bits {
+                    BinaryenUnary(module, BinaryenPromoteFloat32(), value)
+                } else {
+                    BinaryenUnary(module, BinaryenDemoteFloat64(), value)
                 }
+            },
+            Some(MiscCast::FloatToInt(src_bits, (dest_bits, signed))) => {
+                self.emit_float_to_int(value, src_bits, dest_bits, signed)
             }
-            Lvalue::Temp(i) => {
-                match self.temp_map[i.index()] {
-                    Some(i) => i as u32,
-                    None => return None,
-                }
+            Some(MiscCast::IntToFloat((src_bits, signed), dest_bits)) => {
+                let op = match (src_bits > 32, dest_bits == 64, signed) {
+                    (false, false, true) => BinaryenConvertSInt32ToFloat32(),
+                    (false, false, false) => BinaryenConvertUInt32ToFloat32(),
+                    (false, true, true) => BinaryenConvertSInt32ToFloat64(),
+                    (false, true, false) => BinaryenConvertUInt32ToFloat64(),
+                    (true, false, true) => BinaryenConvertSInt64ToFloat32(),
+                    (true, false, false) => BinaryenConvertUInt64ToFloat32(),
+                    (true, true, true) => BinaryenConvertSInt64ToFloat64(),
+                    (true, true, false) => BinaryenConvertUInt64ToFloat64(),
+                };
+                unsafe { BinaryenUnary(module, op, value) }
             }
-            Lvalue::ReturnPointer => {
-                debug!("Translating ret_var lval. self.ret_var = {:?}",
-                       self.ret_var);
-                match self.ret_var {
-                    Some(x) => x.index() as u32,
-                    None => return None,
-                }
+            Some(MiscCast::FatPtr(true)) => value,
+            Some(MiscCast::FatPtr(false)) => {
+                // keep the data pointer, the first word of the fat pointer
+                let ptr = self.emit_checked_ptr(value);
+                unsafe { BinaryenLoad(module, 4, 0, 0, 0, BinaryenInt32(), ptr) }
             }
-            Lvalue::Projection(ref projection) => {
-                let base = match self.trans_lval(&projection.base) {
-                    Some(base) => base,
-                    None => return None,
+            Some(MiscCast::Int(src, (dest_bits, dest_signed))) => {
+                self.emit_int_cast(value, src, dest_bits, dest_signed)
+            }
+            None => {
+                self.unsupported(UnsupportedKind::Cast,
+                                 format!("Cast from {:?} to {:?}", src_ty, dest_ty))
+            }
+        }
+    }
+
+    /// Truncates or extends an integer to `dest_bits`. Integers narrower than 32 bits are kept
+    /// sign or zero extended to 32 bits in their i32.
+    fn emit_int_cast(&mut self,
+                     value: BinaryenExpressionRef,
+                     (src_bits, src_signed): (u32, bool),
+                     dest_bits: u32,
+                     dest_signed: bool)
+                     -> BinaryenExpressionRef {
+        let module = self.func.module.module;
+        unsafe {
+            if src_bits > 32 && dest_bits > 32 {
+                return value;
+            }
+            if dest_bits > 32 {
+                let op = if src_signed {
+                    BinaryenExtendSInt32()
+                } else {
+                    BinaryenExtentUInt32()
                 };
-                let base_ty = projection.base.ty(self.mir, *self.tcx).to_ty(*self.tcx);
-                let base_layout = self.type_layout(base_ty);
+                return BinaryenUnary(module, op, value);
+            }
 
-                match projection.elem {
-                    ProjectionElem::Deref => {
-                        if base.offset.is_none() {
-                            return Some(BinaryenLvalue::new(base.index, None, LvalueExtra::None));
-                        }
-                        panic!("unimplemented Deref {:?}", lvalue);
-                    }
-                    ProjectionElem::Field(ref field, _) => {
-                        let variant = match *base_layout {
-                            Layout::Univariant { ref variant, .. } => variant,
-                            Layout::General { ref variants, .. } => {
-                                if let LvalueExtra::DowncastVariant(variant_idx) = base.extra {
-                                    &variants[variant_idx]
-                                } else {
-                                    panic!("field access on enum had no variant index");
-                                }
-                            }
-                            _ => panic!("unimplemented Field Projection: {:?}", projection),
-                        };
+            let value = if src_bits > 32 {
+                BinaryenUnary(module, BinaryenWrapInt64(), value)
+            } else {
+                value
+            };
 
-                        let offset = variant.field_offset(field.index()).bytes() as u32;
-                        return Some(BinaryenLvalue::new(base.index,
-                                                        base.offset,
-                                                        LvalueExtra::None)
-                            .offset(offset));
-                    }
-                    ProjectionElem::Downcast(_, variant) => {
-                        match *base_layout {
-                            Layout::General { discr, .. } => {
-                                assert!(base.offset.is_none(),
-                                        "unimplemented Downcast Projection with offset");
+            // widening keeps the value as is, if the extension it was stored with is right
+            let already_extended = src_bits <= 32 &&
+                                   (dest_bits > src_bits && !src_signed ||
+                                    dest_bits >= src_bits && src_signed == dest_signed);
+            if dest_bits == 32 || already_extended {
+                return value;
+            }
 
-                                let offset = discr.size().bytes() as u32;
-                                return Some(
-                                    BinaryenLvalue::new(base.index, Some(offset),
-                                                        LvalueExtra::DowncastVariant(variant)));
-                            }
-                            _ => panic!("unimplemented Downcast Projection: {:?}", projection),
-                        }
-                    }
-                    _ => panic!("unimplemented Projection: {:?}", projection),
-                }
+            let shift = 32 - dest_bits as i32;
+            if dest_signed {
+                let shift_left = BinaryenBinary(module,
+                                                BinaryenShlInt32(),
+                                                value,
+                                                BinaryenConst(module, BinaryenLiteralInt32(shift)));
+                BinaryenBinary(module,
+                               BinaryenShrSInt32(),
+                               shift_left,
+                               BinaryenConst(module, BinaryenLiteralInt32(shift)))
+            } else {
+                let mask = BinaryenConst(module, BinaryenLiteralInt32((1 << dest_bits) - 1));
+                BinaryenBinary(module, BinaryenAndInt32(), value, mask)
             }
-            _ => panic!("unimplemented Lvalue: {:?}", lvalue),
+        }
+    }
+
+    /// Float to int `as` casts saturate at the bounds of the integer type, and map NaN to 0,
+    /// where the wasm truncations would trap.
+    fn emit_float_to_int(&mut self,
+                         value: BinaryenExpressionRef,
+                         float_bits: u32,
+                         int_bits: u32,
+                         signed: bool)
+                         -> BinaryenExpressionRef {
+        let wide_float = float_bits == 64;
+        let wide_int = int_bits == 64;
+        let (float_repr, float_ty) = if wide_float {
+            (builder::ReprType::Float64, BinaryenFloat64())
+        } else {
+            (builder::ReprType::Float32, BinaryenFloat32())
         };
+        let tmp: BinaryenIndex = self.func.create_local(float_repr).index().into();
 
-        Some(BinaryenLvalue::new(BinaryenIndex(i), None, LvalueExtra::None))
-    }
+        let (min, max) = if signed {
+            if wide_int {
+                (i64::min_value(), i64::max_value())
+            } else {
+                (-(1i64 << (int_bits - 1)), (1i64 << (int_bits - 1)) - 1)
+            }
+        } else if wide_int {
+            (0, u64::max_value() as i64)
+        } else {
+            (0, ((1u64 << int_bits) - 1) as i64)
+        };
+        // the smallest float above the int range, exactly representable as a power of two
+        let value_bits = if signed { int_bits - 1 } else { int_bits };
+        let upper = 2f64.powi(value_bits as i32);
 
-    fn trans_operand(&mut self, operand: &Operand<'tcx>) -> BinaryenExpressionRef {
-        match *operand {
-            Operand::Consume(ref lvalue) => {
-                let binaryen_lvalue = match self.trans_lval(lvalue) {
+        let module = self.func.module.module;
+        unsafe {
+            let fconst = |x: f64| if wide_float {
+                BinaryenConst(module, BinaryenLiteralFloat64(x))
+            } else {
+                BinaryenConst(module, BinaryenLiteralFloat32(x as f32))
+            };
+            let iconst = |x: i64| if wide_int {
+                BinaryenConst(module, BinaryenLiteralInt64(x))
+            } else {
+                BinaryenConst(module, BinaryenLiteralInt32(x as i32))
+            };
+            let get = || BinaryenGetLocal(module, tmp, float_ty);
+            let (ge, lt, gt, ne) = if wide_float {
+                (BinaryenGeFloat64(), BinaryenLtFloat64(), BinaryenGtFloat64(), BinaryenNeFloat64())
+            } else {
+                (BinaryenGeFloat32(), BinaryenLtFloat32(), BinaryenGtFloat32(), BinaryenNeFloat32())
+            };
+            let trunc = match (wide_float, wide_int, signed) {
+                (false, false, true) => BinaryenTruncSFloat32ToInt32(),
+                (false, false, false) => BinaryenTruncUFloat32ToInt32(),
+                (false, true, true) => BinaryenTruncSFloat32ToInt64(),
+                (false, true, false) => BinaryenTruncUFloat32ToInt64(),
+                (true, false, true) => BinaryenTruncSFloat64ToInt32(),
+                (true, false, false) => BinaryenTruncUFloat64ToInt32(),
+                (true, true, true) => BinaryenTruncSFloat64ToInt64(),
+                (true, true, false) => BinaryenTruncUFloat64ToInt64(),
+            };
+
+            let truncated = BinaryenUnary(module, trunc, get());
+            let not_above_max = if signed {
+                let below_min = BinaryenBinary(module, lt, get(), fconst(-upper));
+                BinaryenIf(module, below_min, iconst(min), truncated)
+            } else {
+                truncated
+            };
+            let above_max = BinaryenBinary(module, ge, get(), fconst(upper));
+            let not_nan = BinaryenIf(module, above_max, iconst(max), not_above_max);
+
+            // the first comparison also saves the value for the others
+            let first = BinaryenTeeLocal(module, tmp, value);
+            if signed {
+                let is_nan = BinaryenBinary(module, ne, first, get());
+                BinaryenIf(module, is_nan, iconst(0), not_nan)
+            } else {
+                // NaN isn't greater than anything either
+                let above_min = BinaryenBinary(module, gt, first, fconst(-1.0));
+                let below_min = BinaryenUnary(module, BinaryenEqZInt32(), above_min);
+                BinaryenIf(module, below_min, iconst(0), not_nan)
+            }
+        }
+    }
+
+    /// Coerces a thin pointer to a fat pointer, to a slice for arrays or to a trait object.
+    /// Fat pointers are stored in linear memory as a (data pointer, length or vtable) pair.
+    fn trans_unsize(&mut self,
+                    operand: &Operand<'tcx>,
+                    src_ty: Ty<'tcx>,
+                    dest: BinaryenLvalue,
+                    dest_ty: Ty<'tcx>,
+                    statements: &mut Vec<BinaryenExpressionRef>) {
+        let extra = match unsize_extra(*self.tcx, src_ty, dest_ty) {
+            Some(extra) => extra,
+            None => {
+                self.unsupported(UnsupportedKind::Cast,
+                                 format!("Unsize Cast from {:?} to {:?}", src_ty, dest_ty));
+                return;
+            }
+        };
+
+        let data = self.trans_operand(operand);
+        let module = self.func.module.module;
+        let extra = match extra {
+            UnsizeExtra::Length(len) => unsafe {
+                BinaryenConst(module, BinaryenLiteralInt32(len as i32))
+            },
+            UnsizeExtra::Vtable(trait_ref) => {
+                let vtable = self.get_vtable(trait_ref) as i32;
+                unsafe { BinaryenConst(module, BinaryenLiteralInt32(vtable)) }
+            }
+        };
+
+        let offset = match dest.offset {
+            Some(offset) => offset,
+            None => {
+                debug!("allocating fat pointer in linear memory to SetLocal({})",
+                       dest.index.0);
+                statements.push(self.emit_frame_slot(dest.index));
+                0
+            }
+        };
+        unsafe {
+            let ptr = self.emit_checked_ptr(BinaryenGetLocal(module, dest.index, BinaryenInt32()));
+            statements.push(BinaryenStore(module, 4, offset, 0, ptr, data, BinaryenInt32()));
+            let ptr = self.emit_checked_ptr(BinaryenGetLocal(module, dest.index, BinaryenInt32()));
+            statements.push(BinaryenStore(module, 4, offset + 4, 0, ptr, extra, BinaryenInt32()));
+        }
+    }
+
+    /// Bit width and signedness of the integer representation of `ty`, see `int_repr`.
+    fn int_repr(&self, ty: Ty<'tcx>) -> Option<(u32, bool)> {
+        int_repr(ty, self.type_layout(ty))
+    }
+
+    /// Whether values of type `ty` live in linear memory, with locals holding their address,
+    /// rather than in wasm locals.
+    fn lives_in_memory(&self, ty: Ty<'tcx>) -> bool {
+        if self.is_zst(ty) {
+            return false;
+        }
+        match *self.type_layout(ty) {
+            Layout::Univariant { .. } |
+            Layout::General { .. } |
+            Layout::Array { .. } |
+            Layout::FatPointer { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Lays out a slot in the frame for each var, temp and return value living in linear memory,
+    /// honoring their alignment.
+    fn layout_frame(&self, base_local: BinaryenIndex) -> Frame {
+        let mut locals = Vec::new();
+        for (decl, local) in self.mir.var_decls.iter().zip(&self.var_map) {
+            if let Some(local) = *local {
+                locals.push((local, decl.ty));
+            }
+        }
+        for (decl, local) in self.mir.temp_decls.iter().zip(&self.temp_map) {
+            if let Some(local) = *local {
+                locals.push((local, decl.ty));
+            }
+        }
+        if let Some(local) = self.ret_var {
+            locals.push((local, self.sig.output));
+        }
+
+        let mut size = 0;
+        let mut slots = HashMap::new();
+        for (local, ty) in locals {
+            if !self.lives_in_memory(ty) {
+                continue;
+            }
+            let layout = self.type_layout(ty);
+            let align = layout.align(&self.tcx.data_layout).abi();
+            size = (size + align - 1) & !(align - 1);
+            slots.insert(local as u32, size as u32);
+            size += layout.size(&self.tcx.data_layout).bytes();
+        }
+
+        Frame {
+            base_local: base_local,
+            size: ((size + STACK_ALIGN - 1) & !(STACK_ALIGN - 1)) as u32,
+            slots: slots,
+        }
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frame.as_ref().expect("the frame is laid out before translating any statement")
+    }
+
+    /// Points `dest` to its slot in the frame.
+    fn emit_frame_slot(&mut self, dest: BinaryenIndex) -> BinaryenExpressionRef {
+        let (base_local, offset) = {
+            let frame = self.frame();
+            (frame.base_local, frame.slots.get(&dest.0).cloned())
+        };
+        let offset = match offset {
+            Some(offset) => offset,
+            None => {
+                return self.unsupported(UnsupportedKind::Lvalue,
+                                        format!("value in linear memory for wasm local {}",
+                                                dest.0))
+            }
+        };
+        debug!("emitting SetLocal({}) of its frame slot, offset {}", dest.0, offset);
+        unsafe {
+            let module = self.func.module.module;
+            let base = BinaryenGetLocal(module, base_local, BinaryenInt32());
+            let offset = BinaryenConst(module, BinaryenLiteralInt32(offset as i32));
+            let address = BinaryenBinary(module, BinaryenAddInt32(), base, offset);
+            BinaryenSetLocal(module, dest, address)
+        }
+    }
+
+    /// The function prologue, allocating the frame: the only stack pointer decrement. Fns which
+    /// don't allocate in linear memory have no frame, and no prologue.
+    fn emit_prologue(&mut self) -> Option<BinaryenExpressionRef> {
+        let (base_local, size) = {
+            let frame = self.frame();
+            (frame.base_local, frame.size)
+        };
+        if size == 0 {
+            debug!("fn {:?} is frameless, skipping the prologue",
+                   self.tcx.item_path_str(self.did));
+            return None;
+        }
+
+        let mut statements = Vec::new();
+        if self.stack_check != StackCheck::Off {
+            statements.push(self.emit_stack_check(size));
+        }
+
+        debug!("emitting function prologue, GetGlobal - {} + TeeLocal({}) + SetGlobal",
+               size,
+               base_local.0);
+        unsafe {
+            let module = self.func.module.module;
+            let size = BinaryenConst(module, BinaryenLiteralInt32(size as i32));
+            let base = BinaryenBinary(module, BinaryenSubInt32(), self.emit_read_sp(), size);
+            let base = BinaryenTeeLocal(module, base_local, base);
+            statements.push(self.stack_pointer.set(self.func.module, base));
+            Some(BinaryenBlock(module,
+                               ptr::null(),
+                               statements.as_ptr(),
+                               BinaryenIndex(statements.len() as _)))
+        }
+    }
+
+    /// Traps, after calling the overflow handler if there's one, when a frame of `size` bytes
+    /// doesn't fit above the stack limit. Comparing with the stack pointer before the decrement
+    /// also catches frames which would wrap around below address 0.
+    fn emit_stack_check(&mut self, size: u32) -> BinaryenExpressionRef {
+        debug!("emitting {:?} stack overflow check for a {} bytes frame",
+               self.stack_check,
+               size);
+        let module = self.func.module.module;
+        let overflow = if self.stack_check == StackCheck::Handler {
+            let handler = self.runtime_fn(RuntimeFn::StackOverflow);
+            unsafe {
+                let statements = [BinaryenCallImport(module,
+                                                     handler,
+                                                     ptr::null(),
+                                                     BinaryenIndex(0),
+                                                     BinaryenNone()),
+                                  BinaryenUnreachable(module)];
+                BinaryenBlock(module,
+                              ptr::null(),
+                              statements.as_ptr(),
+                              BinaryenIndex(statements.len() as _))
+            }
+        } else {
+            unsafe { BinaryenUnreachable(module) }
+        };
+
+        unsafe {
+            let size = BinaryenConst(module, BinaryenLiteralInt32(size as i32));
+            let lowest_sp = BinaryenBinary(module,
+                                           BinaryenAddInt32(),
+                                           self.stack_limit.get(self.func.module),
+                                           size);
+            let overflows =
+                BinaryenBinary(module, BinaryenLtUInt32(), self.emit_read_sp(), lowest_sp);
+            BinaryenIf(module, overflows, overflow, BinaryenExpressionRef(ptr::null_mut()))
+        }
+    }
+
+    fn emit_read_sp(&self) -> BinaryenExpressionRef {
+        self.stack_pointer.get(self.func.module)
+    }
+
+    /// The function epilogue, freeing the frame to restore the caller's stack pointer, and the
+    /// `Return`. Like the prologue, frameless fns have no epilogue.
+    fn emit_return(&mut self, statements: &mut Vec<BinaryenExpressionRef>) {
+        unsafe {
+            let module = self.func.module.module;
+            let frame = self.frame();
+            if frame.size > 0 {
+                debug!("emitting function epilogue, GetLocal({}) + {} + SetGlobal",
+                       frame.base_local.0,
+                       frame.size);
+                let base = BinaryenGetLocal(module, frame.base_local, BinaryenInt32());
+                let size = BinaryenConst(module, BinaryenLiteralInt32(frame.size as i32));
+                let caller_sp = BinaryenBinary(module, BinaryenAddInt32(), base, size);
+                statements.push(self.stack_pointer.set(self.func.module, caller_sp));
+            }
+        }
+
+        let expr = if self.ret_var.is_none() {
+            BinaryenExpressionRef(ptr::null_mut())
+        } else {
+            self.trans_operand(&Operand::Consume(Lvalue::ReturnPointer))
+        };
+        statements.push(unsafe { BinaryenReturn(self.func.module.module, expr) });
+    }
+
+    fn emit_panic_flag(&self) -> BinaryenExpressionRef {
+        self.panic_flag.get(self.func.module)
+    }
+
+    fn emit_set_panic_flag(&self, panicking: bool) -> BinaryenExpressionRef {
+        unsafe {
+            let value = BinaryenConst(self.func.module.module,
+                                      BinaryenLiteralInt32(panicking as i32));
+            self.panic_flag.set(self.func.module, value)
+        }
+    }
+
+    /// Branches from a block ending with a call to its `target`. When unwinding, a call which
+    /// panicked, and set the panic flag, continues to `unwind` instead.
+    fn emit_call_edges(&self,
+                       from: RelooperBlockRef,
+                       target: Option<RelooperBlockRef>,
+                       unwind: Option<RelooperBlockRef>,
+                       can_panic: bool) {
+        let unwind = if self.panic_strategy == PanicStrategy::Unwind && can_panic {
+            unwind
+        } else {
+            None
+        };
+        unsafe {
+            match (target, unwind) {
+                (Some(target), Some(unwind)) => {
+                    RelooperAddBranch(from,
+                                      unwind,
+                                      self.emit_panic_flag(),
+                                      BinaryenExpressionRef(ptr::null_mut()));
+                    RelooperAddBranch(from,
+                                      target,
+                                      BinaryenExpressionRef(ptr::null_mut()),
+                                      BinaryenExpressionRef(ptr::null_mut()));
+                }
+                (Some(target), None) => {
+                    RelooperAddBranch(from,
+                                      target,
+                                      BinaryenExpressionRef(ptr::null_mut()),
+                                      BinaryenExpressionRef(ptr::null_mut()));
+                }
+                // a diverging call only returns when unwinding
+                (None, Some(unwind)) => {
+                    RelooperAddBranch(from,
+                                      unwind,
+                                      BinaryenExpressionRef(ptr::null_mut()),
+                                      BinaryenExpressionRef(ptr::null_mut()));
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    /// Calls the destructor of the `location`'s type, if it has one, and drops its fields.
+    /// Returns whether a destructor was called, which could panic.
+    fn trans_drop(&mut self,
+                  location: &Lvalue<'tcx>,
+                  statements: &mut Vec<BinaryenExpressionRef>)
+                  -> bool {
+        let ty = self.monomorphize(location.ty(self.mir, *self.tcx).to_ty(*self.tcx));
+        if !needs_drop(*self.tcx, ty) {
+            debug!("skipping Drop of {:?}, with type {:?} not needing drop", location, ty);
+            return false;
+        }
+
+        let field_tys = match drop_field_tys(*self.tcx, ty) {
+            Some(field_tys) => field_tys,
+            None => {
+                self.unsupported(UnsupportedKind::Terminator,
+                                 format!("Drop of {:?}, with type {:?}", location, ty));
+                return false;
+            }
+        };
+
+        let mut called_dtor = false;
+        if let ty::TyAdt(adt_def, substs) = ty.sty {
+            if let Some(dtor) = adt_def.destructor() {
+                let dest = self.trans_lval(location);
+                let fn_key = match self.trans_fn_instance(dtor, substs) {
+                    Some(fn_key) => fn_key,
+                    None => return false,
+                };
+                debug!("emitting Call to Drop impl of {:?} for {:?}", ty, location);
+                unsafe {
+                    let module = self.func.module.module;
+                    let ptr = match dest {
+                        Some(dest) => {
+                            let mut ptr = BinaryenGetLocal(module, dest.index, BinaryenInt32());
+                            if let Some(offset) = dest.offset {
+                                let offset =
+                                    BinaryenConst(module, BinaryenLiteralInt32(offset as i32));
+                                ptr = BinaryenBinary(module, BinaryenAddInt32(), ptr, offset);
+                            }
+                            ptr
+                        }
+                        None => {
+                            // like references to them, zero-sized values are at a dangling,
+                            // aligned address
+                            let align = self.type_layout(ty).align(&self.tcx.data_layout).abi();
+                            BinaryenConst(module, BinaryenLiteralInt32(align as i32))
+                        }
+                    };
+                    let args = [ptr];
+                    statements.push(BinaryenCall(module,
+                                                 self.fun_names[&fn_key].as_ptr(),
+                                                 args.as_ptr(),
+                                                 BinaryenIndex(args.len() as _),
+                                                 BinaryenNone()));
+                }
+                called_dtor = true;
+            }
+        }
+
+        for (i, field_ty) in field_tys.into_iter().enumerate() {
+            let field = Lvalue::Projection(Box::new(LvalueProjection {
+                base: location.clone(),
+                elem: ProjectionElem::Field(Field::new(i), field_ty),
+            }));
+            called_dtor |= self.trans_drop(&field, statements);
+        }
+        called_dtor
+    }
+
+    fn emit_load(&self,
+                 ty: Ty<'tcx>,
+                 ptr: BinaryenExpressionRef,
+                 offset: u32)
+                 -> BinaryenExpressionRef {
+        let (bytes, signed, wasm_ty) = rust_ty_mem_access(ty);
+        let ptr = self.emit_checked_ptr(ptr);
+        unsafe {
+            BinaryenLoad(self.func.module.module,
+                         bytes,
+                         signed as u8,
+                         offset,
+                         0,
+                         wasm_ty,
+                         ptr)
+        }
+    }
+
+    fn emit_store(&self,
+                  ty: Ty<'tcx>,
+                  ptr: BinaryenExpressionRef,
+                  offset: u32,
+                  value: BinaryenExpressionRef)
+                  -> BinaryenExpressionRef {
+        let (bytes, _, wasm_ty) = rust_ty_mem_access(ty);
+        let ptr = self.emit_checked_ptr(ptr);
+        unsafe { BinaryenStore(self.func.module.module, bytes, offset, 0, ptr, value, wasm_ty) }
+    }
+
+    /// In debug builds, traps when the address computed by `ptr`, the pointer of a load or store,
+    /// is in the guard region, like a null pointer, and otherwise yields it. In release builds,
+    /// this is just `ptr`.
+    fn emit_checked_ptr(&self, ptr: BinaryenExpressionRef) -> BinaryenExpressionRef {
+        let ptr_local = match self.guard_ptr_local {
+            Some(ptr_local) => ptr_local,
+            None => return ptr,
+        };
+
+        debug!("emitting guard region check of the pointer in SetLocal({})", ptr_local.0);
+        unsafe {
+            let module = self.func.module.module;
+            let set_ptr = BinaryenSetLocal(module, ptr_local, ptr);
+            let address = BinaryenGetLocal(module, ptr_local, BinaryenInt32());
+            let guard_end = BinaryenConst(module, BinaryenLiteralInt32(GUARD_REGION_SIZE as i32));
+            let in_guard = BinaryenBinary(module, BinaryenLtUInt32(), address, guard_end);
+            let check = BinaryenIf(module,
+                                   in_guard,
+                                   BinaryenUnreachable(module),
+                                   BinaryenExpressionRef(ptr::null_mut()));
+            let children = [set_ptr, check, BinaryenGetLocal(module, ptr_local, BinaryenInt32())];
+            BinaryenBlock(module,
+                          ptr::null(),
+                          children.as_ptr(),
+                          BinaryenIndex(children.len() as _))
+        }
+    }
+
+    /// Writes `value` to `dest`, which is either a wasm local or a location in linear memory.
+    fn emit_assign(&self,
+                   dest: BinaryenLvalue,
+                   dest_ty: Ty<'tcx>,
+                   value: BinaryenExpressionRef)
+                   -> BinaryenExpressionRef {
+        unsafe {
+            match dest.offset {
+                Some(offset) => {
+                    let ptr =
+                        BinaryenGetLocal(self.func.module.module, dest.index, BinaryenInt32());
+                    self.emit_store(dest_ty, ptr, offset, value)
+                }
+                None => BinaryenSetLocal(self.func.module.module, dest.index, value),
+            }
+        }
+    }
+
+    fn emit_assign_fields<I>(&mut self,
+                             dest: BinaryenIndex,
+                             offsets: I,
+                             operands: &[Operand<'tcx>],
+                             statements: &mut Vec<BinaryenExpressionRef>)
+        where I: IntoIterator<Item = u64>
+    {
+        unsafe {
+            for (offset, operand) in offsets.into_iter().zip(operands) {
+                if self.is_zst(operand.ty(self.mir, *self.tcx)) {
+                    continue;
+                }
+                // let operand_ty = self.mir.operand_ty(*self.tcx, operand);
+                // TODO: match on the operand_ty to know how many bytes to store, not just i32s
+                let src = self.trans_operand(operand);
+                let ptr = BinaryenGetLocal(self.func.module.module, dest, BinaryenInt32());
+                let ptr = self.emit_checked_ptr(ptr);
+                let write_field = BinaryenStore(self.func.module.module,
+                                                4,
+                                                offset as u32,
+                                                0,
+                                                ptr,
+                                                src,
+                                                BinaryenInt32());
+                statements.push(write_field);
+            }
+        }
+    }
+
+    /// Returns `None` for zero-sized lvalues, which have no storage.
+    fn trans_lval(&mut self, lvalue: &Lvalue<'tcx>) -> Option<BinaryenLvalue> {
+        let lvalue_ty = lvalue.ty(self.mir, *self.tcx).to_ty(*self.tcx);
+        if self.is_zst(lvalue_ty) {
+            return None;
+        }
+
+        let i = match *lvalue {
+            Lvalue::Arg(i) => {
+                match self.arg_map[i.index()] {
+                    Some(i) => i as u32,
+                    None => return None,
+                }
+            }
+            Lvalue::Var(i) => {
+                match self.var_map[i.index()] {
+                    Some(i) => i as u32,
+                    None => return None,
+                }
+            }
+            Lvalue::Temp(i) => {
+                match self.temp_map[i.index()] {
+                    Some(i) => i as u32,
+                    None => return None,
+                }
+            }
+            Lvalue::ReturnPointer => {
+                debug!("Translating ret_var lval. self.ret_var = {:?}",
+                       self.ret_var);
+                match self.ret_var {
+                    Some(x) => x.index() as u32,
+                    None => return None,
+                }
+            }
+            Lvalue::Projection(ref projection) => {
+                let base = match self.trans_lval(&projection.base) {
+                    Some(base) => base,
+                    None => return None,
+                };
+                let base_ty = projection.base.ty(self.mir, *self.tcx).to_ty(*self.tcx);
+                let base_layout = self.type_layout(base_ty);
+
+                match projection.elem {
+                    ProjectionElem::Deref => {
+                        if base.offset.is_none() {
+                            return Some(BinaryenLvalue::new(base.index, None, LvalueExtra::None));
+                        }
+                        return self.unsupported_lval(format!("Deref {:?}", lvalue));
+                    }
+                    ProjectionElem::Field(ref field, _) => {
+                        let variant = match *base_layout {
+                            Layout::Univariant { ref variant, .. } => variant,
+                            Layout::General { ref variants, .. } => {
+                                if let LvalueExtra::DowncastVariant(variant_idx) = base.extra {
+                                    &variants[variant_idx]
+                                } else {
+                                    return self.unsupported_lval(format!("Field Projection of \
+                                                                          enum without variant: \
+                                                                          {:?}",
+                                                                         projection));
+                                }
+                            }
+                            _ => {
+                                return self.unsupported_lval(format!("Field Projection: {:?}",
+                                                                     projection))
+                            }
+                        };
+
+                        let offset = variant.field_offset(field.index()).bytes() as u32;
+                        return Some(BinaryenLvalue::new(base.index,
+                                                        base.offset,
+                                                        LvalueExtra::None)
+                            .offset(offset));
+                    }
+                    ProjectionElem::Downcast(_, variant) => {
+                        match *base_layout {
+                            Layout::General { discr, .. } => {
+                                if base.offset.is_some() {
+                                    return self.unsupported_lval(format!("Downcast Projection \
+                                                                          with offset: {:?}",
+                                                                         projection));
+                                }
+
+                                let offset = discr.size().bytes() as u32;
+                                return Some(
+                                    BinaryenLvalue::new(base.index, Some(offset),
+                                                        LvalueExtra::DowncastVariant(variant)));
+                            }
+                            _ => {
+                                return self.unsupported_lval(format!("Downcast Projection: {:?}",
+                                                                     projection))
+                            }
+                        }
+                    }
+                    _ => return self.unsupported_lval(format!("Projection: {:?}", projection)),
+                }
+            }
+            _ => return self.unsupported_lval(format!("Lvalue: {:?}", lvalue)),
+        };
+
+        Some(BinaryenLvalue::new(BinaryenIndex(i), None, LvalueExtra::None))
+    }
+
+    /// Zero-sized operands have no value, and translate to a `Nop`: their consumers are expected
+    /// to skip them, as zero-sized lvalues, args and returns have no wasm counterpart.
+    fn trans_operand(&mut self, operand: &Operand<'tcx>) -> BinaryenExpressionRef {
+        if self.is_zst(operand.ty(self.mir, *self.tcx)) {
+            debug!("operand is zero-sized: {:?}", operand);
+            return unsafe { BinaryenNop(self.func.module.module) };
+        }
+
+        match *operand {
+            Operand::Consume(ref lvalue) => {
+                let binaryen_lvalue = match self.trans_lval(lvalue) {
                     Some(lval) => lval,
                     None => {
-                        debug!("operand lval is unit: {:?}", operand);
-                        return unsafe { BinaryenUnreachable(self.func.module.module) };
+                        return self.unsupported(UnsupportedKind::Lvalue,
+                                                format!("untranslated lvalue for operand {:?}",
+                                                        operand));
                     }
                 };
                 let lval_ty = lvalue.ty(self.mir, *self.tcx);
                 let t = lval_ty.to_ty(*self.tcx);
-                let t = rust_ty_to_binaryen(t);
+                let t = rust_ty_to_binaryen(self.monomorphize(t));
 
                 unsafe {
                     match binaryen_lvalue.offset {
@@ -1411,6 +3078,7 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                                    lvalue);
                             let ptr =
                                 BinaryenGetLocal(self.func.module_ref(), binaryen_lvalue.index, t);
+                            let ptr = self.emit_checked_ptr(ptr);
                             // TODO: match on the field ty to know how many bytes to read, not just
                             // i32s
                             BinaryenLoad(self.func.module.module,
@@ -1428,169 +3096,1126 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
                     }
                 }
             }
-            Operand::Constant(ref c) => {
-                match c.literal {
-                    Literal::Value { ref value } => {
-                        // TODO: handle more Rust types here
-                        unsafe {
-                            let lit = match *value {
-                                ConstVal::Integral(ConstInt::Isize(ConstIsize::Is32(val))) |
-                                ConstVal::Integral(ConstInt::I32(val)) => BinaryenLiteralInt32(val),
-                                // TODO: Since we're at the wasm32 stage, and until wasm64, it's
-                                // probably best if isize is always i32 ?
-                                ConstVal::Integral(ConstInt::Isize(ConstIsize::Is64(val))) => {
-                                    BinaryenLiteralInt32(val as i32)
-                                }
-                                ConstVal::Integral(ConstInt::I64(val)) => BinaryenLiteralInt64(val),
-                                ConstVal::Bool(val) => {
-                                    let val = if val { 1 } else { 0 };
-                                    BinaryenLiteralInt32(val)
-                                }
-                                _ => panic!("unimplemented value: {:?}", value),
-                            };
-                            BinaryenConst(self.func.module.module, lit)
-                        }
+            Operand::Constant(ref c) => {
+                match c.literal {
+                    Literal::Value { ref value } => {
+                        let ty = operand.ty(self.mir, *self.tcx);
+                        match const_literal(value, ty) {
+                            Some(lit) => unsafe { BinaryenConst(self.func.module.module, lit) },
+                            None => {
+                                self.unsupported(UnsupportedKind::Operand,
+                                                 format!("constant value: {:?}", value))
+                            }
+                        }
+                    }
+                    Literal::Promoted { .. } => {
+                        self.unsupported(UnsupportedKind::Operand,
+                                         format!("Promoted Literal: {:?}", c))
+                    }
+                    _ => {
+                        self.unsupported(UnsupportedKind::Operand,
+                                         format!("Constant Literal {:?}", c))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Zero-sized types, like `()`, empty structs and arrays, `PhantomData` or fn items, have
+    /// no storage and no value. Unsized types, like `str`, slices and trait objects, also have a
+    /// layout of size 0, but live behind pointers and are not zero-sized.
+    fn is_zst(&self, ty: Ty<'tcx>) -> bool {
+        ty.is_never() || is_zst_layout(self.type_layout(ty), &self.tcx.data_layout)
+    }
+
+    /// The type of the wasm local holding a `ty`, if it needs one.
+    fn local_ty(&self, ty: Ty<'tcx>) -> builder::Type {
+        if self.is_zst(ty) {
+            None
+        } else {
+            rust_ty_to_builder(self.monomorphize(ty))
+        }
+    }
+
+    /// Substitutes the type parameters of the fn instance in a type of its MIR.
+    fn monomorphize(&self, ty: Ty<'tcx>) -> Ty<'tcx> {
+        monomorphize::apply_ty_substs(self.tcx, self.substs, ty)
+    }
+
+    #[inline]
+    fn type_size(&self, ty: Ty<'tcx>) -> usize {
+        self.type_size_with_substs(ty, self.substs)
+    }
+
+    // Imported from miri
+    #[inline]
+    fn type_size_with_substs(&self, ty: Ty<'tcx>, substs: &'tcx Substs<'tcx>) -> usize {
+        self.type_layout_with_substs(ty, substs).size(&self.tcx.data_layout).bytes() as usize
+    }
+
+    #[inline]
+    fn type_layout(&self, ty: Ty<'tcx>) -> &'tcx Layout {
+        self.type_layout_with_substs(ty, self.substs)
+    }
+
+    // Imported from miri and slightly modified to adapt to our monomorphize api
+    fn type_layout_with_substs(&self, ty: Ty<'tcx>, substs: &'tcx Substs<'tcx>) -> &'tcx Layout {
+        // TODO(solson): Is this inefficient? Needs investigation.
+        let ty = monomorphize::apply_ty_substs(self.tcx, substs, ty);
+
+        self.tcx.infer_ctxt(None, None, Reveal::All).enter(|infcx| {
+            match ty.layout(&infcx) {
+                Ok(layout) => layout,
+                Err(error) => {
+                    // the layout of an i32 stands in for it, the fn is not emitted anyway
+                    let message = format!("layout of `{}`: {}", ty, error);
+                    self.record_unsupported(UnsupportedKind::Layout, message);
+                    let error = ErrorKind::Layout(ty.to_string(), error.to_string());
+                    self.errors.borrow_mut().push(error.into());
+                    self.tcx.types.i32.layout(&infcx).unwrap()
+                }
+            }
+        })
+    }
+
+    /// The return type and kind of call of a callee: fn items are called directly, fn pointers
+    /// through the function table. `None` when it's neither.
+    fn trans_callee(&mut self,
+                    operand: &Operand<'tcx>)
+                    -> Option<(BinaryenType, BinaryenCallKind)> {
+        if let ty::TyFnPtr(bare_fn) = operand.ty(self.mir, *self.tcx).sty {
+            let sig = bare_fn.sig.skip_binder();
+            let fn_type = self.fn_ptr_type(sig);
+            let target = self.trans_operand(operand);
+            let ret_ty = if !self.is_zst(sig.output) {
+                rust_ty_to_binaryen(sig.output)
+            } else {
+                BinaryenNone()
+            };
+            return Some((ret_ty, BinaryenCallKind::Indirect(target, fn_type)));
+        }
+
+        match *operand {
+            Operand::Constant(ref c) => {
+                match c.literal {
+                    Literal::Item { def_id, substs } => {
+                        let ty = self.tcx.lookup_item_type(def_id).ty;
+                        // the MIR of the fns of other crates isn't available, their calls are
+                        // reported by the caller
+                        if ty.is_fn() && def_id.is_local() {
+                            let sig = ty.fn_sig().skip_binder();
+
+                            let (fn_key, imported) = if self.is_foreign_fn(def_id) {
+                                // extern fns are imported from the host
+                                self.import_wasm_extern(def_id, sig);
+                                ((def_id, sig.clone()), true)
+                            } else {
+                                match self.trans_fn_instance(def_id, substs) {
+                                    Some(fn_key) => (fn_key, false),
+                                    None => {
+                                        // the failed trait selection has already been reported,
+                                        // and no fn was emitted: call through an unreachable
+                                        // placeholder instead
+                                        let sig = monomorphize::apply_param_substs(self.tcx,
+                                                                                   substs,
+                                                                                   sig);
+                                        let ret_ty = if !self.is_zst(sig.output) {
+                                            rust_ty_to_binaryen(sig.output)
+                                        } else {
+                                            BinaryenNone()
+                                        };
+                                        let fn_type = self.fn_ptr_type(&sig);
+                                        let target = unsafe {
+                                            BinaryenUnreachable(self.func.module.module)
+                                        };
+                                        return Some((ret_ty,
+                                                     BinaryenCallKind::Indirect(target, fn_type)));
+                                    }
+                                }
+                            };
+
+                            let ret_ty = if !self.is_zst(fn_key.1.output) {
+                                rust_ty_to_binaryen(fn_key.1.output)
+                            } else {
+                                BinaryenNone()
+                            };
+
+                            let name = self.fun_names[&fn_key].as_ptr();
+                            let call_kind = if imported {
+                                BinaryenCallKind::Import(name)
+                            } else {
+                                BinaryenCallKind::Direct(name)
+                            };
+                            Some((ret_ty, call_kind))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Translates the monomorphization of a fn item for `substs`, resolving trait methods to
+    /// their impl, unless it has already been translated. Returns its key in `fun_names`, or
+    /// `None` when the trait method can't be resolved, which is reported as unsupported.
+    fn trans_fn_instance(&mut self,
+                         def_id: DefId,
+                         substs: &'tcx Substs<'tcx>)
+                         -> Option<(DefId, ty::FnSig<'tcx>)> {
+        let mut fn_did = def_id;
+        let sig = self.tcx.lookup_item_type(def_id).ty.fn_sig().skip_binder();
+
+        let is_trait_method = self.tcx.trait_of_item(fn_did).is_some();
+
+        let (substs, sig) = if !is_trait_method {
+            (substs, sig)
+        } else {
+            let (resolved_def_id, resolved_substs) =
+                match traits::resolve_trait_method(self.tcx, fn_did, substs) {
+                    Ok(resolved) => resolved,
+                    Err(error) => {
+                        // the trait method's own name stands in for the call's callee
+                        let message = format!("call to `{}`: {}",
+                                              self.tcx.item_path_str(fn_did),
+                                              error);
+                        self.record_unsupported(UnsupportedKind::TraitSelection, message);
+                        self.errors.borrow_mut().push(error);
+                        return None;
+                    }
+                };
+            let ty = self.tcx.lookup_item_type(resolved_def_id).ty;
+            // TODO: investigate rustc trans use of
+            // liberate_bound_regions or similar here
+            let sig = ty.fn_sig().skip_binder();
+
+            fn_did = resolved_def_id;
+            (resolved_substs, sig)
+        };
+
+        let mir = &self.mir_map.map[&fn_did];
+
+        let fn_sig = monomorphize::apply_param_substs(self.tcx, substs, sig);
+
+        // mark the fn defid seen to not have translated twice
+        // TODO: verify this more thoroughly, works for our limited
+        // tests right now
+        if *sig != fn_sig {
+            let fn_name = sanitize_symbol(&self.tcx.item_path_str(fn_did));
+            let fn_name = CString::new(fn_name).expect("");
+            self.fun_names.insert((fn_did, sig.clone()), fn_name);
+        }
+
+        // This simple check is also done in trans() but doing it here
+        // helps have a clearer debug log
+        if !self.fun_names.contains_key(&(fn_did, fn_sig.clone())) {
+            let mut ctxt = BinaryenFnCtxt {
+                tcx: self.tcx,
+                mir_map: self.mir_map,
+                access_levels: self.access_levels,
+                mir: mir,
+                did: fn_did,
+                sig: &fn_sig,
+                substs: substs,
+                func: self.func.module.create_func(),
+                entry_fn: self.entry_fn,
+                fun_types: &mut self.fun_types,
+                fun_names: &mut self.fun_names,
+                fn_refs: &mut self.fn_refs,
+                fn_table: &mut self.fn_table,
+                vtables: &mut self.vtables,
+                static_data: &mut self.static_data,
+                static_strs: &mut self.static_strs,
+                runtime_fns: &mut self.runtime_fns,
+                c_strings: &mut self.c_strings,
+                exports: &mut self.exports,
+                panic_strategy: self.panic_strategy,
+                stack_pointer: self.stack_pointer,
+                panic_flag: self.panic_flag,
+                stack_limit: self.stack_limit,
+                stack_check: self.stack_check,
+                unsupported_mode: self.unsupported_mode,
+                unsupported_fns: &mut self.unsupported_fns,
+                unsupported_count: &mut self.unsupported_count,
+                errors: self.errors,
+                frame: None,
+                span: mir.span,
+                unsupported: RefCell::new(Vec::new()),
+                checked_op_local: None,
+                guard_ptr_local: None,
+                arg_map: Vec::new(),
+                var_map: Vec::new(),
+                temp_map: Vec::new(),
+                ret_var: None,
+            };
+
+            debug!("translating monomorphized fn {:?}",
+                   self.tcx.item_path_str(fn_did));
+            ctxt.trans();
+            debug!("done translating monomorphized {:?}, continuing \
+                    translation of fn {:?}",
+                   self.tcx.item_path_str(fn_did),
+                   self.tcx.item_path_str(self.did));
+        }
+
+        Some((fn_did, fn_sig))
+    }
+
+    /// The wasm function type of the fns a fn pointer with signature `sig` can point to, for
+    /// `call_indirect`.
+    fn fn_ptr_type(&mut self, sig: &ty::FnSig<'tcx>) -> BinaryenFunctionTypeRef {
+        if let Some(&fn_type) = self.fun_types.get(sig) {
+            return fn_type;
+        }
+
+        let arg_tys: Vec<_> = sig.inputs.iter().filter_map(|&ty| self.local_ty(ty)).collect();
+        let ret_ty = self.local_ty(sig.output);
+        let name = format!("rustfnptr-{}", self.fun_types.len());
+        self.c_strings.push(CString::new(name).expect(""));
+        let name = &self.c_strings[self.c_strings.len() - 1];
+        let fn_type = self.func.module.create_function_type(name, &arg_tys, ret_ty).into();
+        self.fun_types.insert(sig.clone(), fn_type);
+        fn_type
+    }
+
+    /// Returns the index of a translated fn in the wasm function table, adding it if necessary.
+    fn fn_table_index(&mut self, fn_key: (DefId, ty::FnSig<'tcx>)) -> u32 {
+        let name = self.fun_names[&fn_key].to_str().expect("").to_owned();
+        match self.fn_table.iter().position(|n| *n == name) {
+            Some(index) => index as u32,
+            None => {
+                debug!("adding fn {} to the function table", name);
+                self.fn_table.push(name);
+                (self.fn_table.len() - 1) as u32
+            }
+        }
+    }
+
+    /// Reserves `size` zeroed bytes of static data, returning their linear memory address.
+    fn alloc_static(&mut self, size: usize, align: usize) -> u32 {
+        while (STATIC_DATA_BASE as usize + self.static_data.len()) % align != 0 {
+            self.static_data.push(0);
+        }
+        let offset = self.static_data.len();
+        self.static_data.resize(offset + size, 0);
+        STATIC_DATA_BASE + offset as u32
+    }
+
+    /// Copies `s` into the static data the first time it is used, and returns its address.
+    fn alloc_str(&mut self, s: &str) -> u32 {
+        if let Some(&address) = self.static_strs.get(s) {
+            return address;
+        }
+        let address = self.alloc_static(s.len(), 1);
+        let offset = (address - STATIC_DATA_BASE) as usize;
+        self.static_data[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+        self.static_strs.insert(s.to_owned(), address);
+        address
+    }
+
+    /// Returns the address of the vtable for `trait_ref`, emitting it into the static data on
+    /// first use. Like rustc's, it holds the drop glue, size and alignment of the concrete type,
+    /// followed by the function table indices of the trait methods.
+    fn get_vtable(&mut self, trait_ref: ty::PolyTraitRef<'tcx>) -> u32 {
+        if let Some(&address) = self.vtables.get(&trait_ref) {
+            return address;
+        }
+
+        let self_ty = trait_ref.self_ty();
+        let size = self.type_size(self_ty) as u32;
+        let align = self.type_layout(self_ty).align(&self.tcx.data_layout).abi() as u32;
+
+        // TODO: there is no drop glue yet, so vtables don't have a destructor
+        let mut entries = vec![0, size, align];
+
+        // TODO: skip generic and `where Self: Sized` methods, which aren't object safe
+        let trait_items = self.tcx.trait_items(trait_ref.def_id());
+        for item in trait_items.iter() {
+            if let ty::MethodTraitItem(ref method) = *item {
+                let fn_key = self.trans_fn_instance(method.def_id, trait_ref.skip_binder().substs);
+                // a failed trait selection has already been reported, and its entry is never
+                // called
+                let index = fn_key.map_or(0, |fn_key| self.fn_table_index(fn_key));
+                entries.push(index);
+            }
+        }
+
+        let address = self.alloc_static(entries.len() * 4, 4);
+        let offset = (address - STATIC_DATA_BASE) as usize;
+        for (i, entry) in entries.iter().enumerate() {
+            for byte in 0..4 {
+                self.static_data[offset + i * 4 + byte] = (*entry >> (byte * 8)) as u8;
+            }
+        }
+
+        debug!("emitted vtable for {:?} at {}: {:?}", trait_ref, address, entries);
+        self.vtables.insert(trait_ref, address);
+        address
+    }
+
+    /// Lowers a call to an `extern "rust-intrinsic"` function inline. Returns `false` if `func`
+    /// is not an intrinsic, in which case the caller emits a regular call.
+    fn trans_intrinsic_call(&mut self,
+                            func: &Operand<'tcx>,
+                            args: &[Operand<'tcx>],
+                            destination: &Option<(Lvalue<'tcx>, BasicBlock)>,
+                            statements: &mut Vec<BinaryenExpressionRef>)
+                            -> bool {
+        let (def_id, substs) = match *func {
+            Operand::Constant(Constant { literal: Literal::Item { def_id, substs }, .. }) => {
+                (def_id, substs)
+            }
+            _ => return false,
+        };
+        match self.tcx.lookup_item_type(def_id).ty.sty {
+            ty::TyFnDef(_, _, ref fn_ty) if fn_ty.abi == Abi::RustIntrinsic => {}
+            // the linear memory builtins for allocators, declared in an extern block
+            _ if self.is_foreign_fn(def_id) &&
+                 self.tcx.item_name(def_id).as_str().starts_with("__wasm_") => {}
+            _ => return false,
+        }
+
+        let name = self.tcx.item_name(def_id).as_str();
+        debug!("lowering intrinsic {} with substs {:?}", name, substs);
 
+        let module = self.func.module.module;
+        let value = unsafe {
+            match &*name {
+                "size_of" => {
+                    let size = self.type_size(substs.type_at(0)) as i32;
+                    Some(BinaryenConst(module, BinaryenLiteralInt32(size)))
+                }
+                "min_align_of" => {
+                    let layout = self.type_layout(substs.type_at(0));
+                    let align = layout.align(&self.tcx.data_layout).abi() as i32;
+                    Some(BinaryenConst(module, BinaryenLiteralInt32(align)))
+                }
+                "transmute" => {
+                    let (src_ty, dest_ty) = (substs.type_at(0), substs.type_at(1));
+                    let src = self.trans_operand(&args[0]);
+                    if self.is_immediate(src_ty) && !self.is_immediate(dest_ty) {
+                        self.emit_transmute_to_aggregate(src, src_ty, destination, statements);
+                        None
+                    } else {
+                        Some(self.emit_transmute(src, src_ty, dest_ty))
+                    }
+                }
+                "copy" | "copy_nonoverlapping" => {
+                    // memmove is also correct for non-overlapping ranges
+                    let src = self.trans_operand(&args[0]);
+                    let dst = self.trans_operand(&args[1]);
+                    let len = self.emit_byte_count(substs.type_at(0), &args[2]);
+                    let memmove = self.runtime_fn(RuntimeFn::Memmove);
+                    let call_args = [dst, src, len];
+                    statements.push(BinaryenCall(module,
+                                                 memmove,
+                                                 call_args.as_ptr(),
+                                                 BinaryenIndex(call_args.len() as _),
+                                                 BinaryenNone()));
+                    None
+                }
+                "write_bytes" => {
+                    let dst = self.trans_operand(&args[0]);
+                    let val = self.trans_operand(&args[1]);
+                    let len = self.emit_byte_count(substs.type_at(0), &args[2]);
+                    let memset = self.runtime_fn(RuntimeFn::Memset);
+                    let call_args = [dst, val, len];
+                    statements.push(BinaryenCall(module,
+                                                 memset,
+                                                 call_args.as_ptr(),
+                                                 BinaryenIndex(call_args.len() as _),
+                                                 BinaryenNone()));
+                    None
+                }
+                "offset" => {
+                    let ptr = self.trans_operand(&args[0]);
+                    let offset = self.emit_byte_count(substs.type_at(0), &args[1]);
+                    Some(BinaryenBinary(module, BinaryenAddInt32(), ptr, offset))
+                }
+                "__wasm_current_memory" => {
+                    Some(BinaryenHost(module,
+                                      BinaryenCurrentMemory(),
+                                      ptr::null(),
+                                      ptr::null(),
+                                      BinaryenIndex(0)))
+                }
+                "__wasm_grow_memory" => {
+                    // returns the previous size in pages, or -1
+                    let pages = [self.trans_operand(&args[0])];
+                    Some(BinaryenHost(module,
+                                      BinaryenGrowMemory(),
+                                      ptr::null(),
+                                      pages.as_ptr(),
+                                      BinaryenIndex(pages.len() as _)))
+                }
+                "__wasm_heap_base" => {
+                    let heap_base = self.runtime_fn(RuntimeFn::HeapBase);
+                    Some(BinaryenCall(module,
+                                      heap_base,
+                                      ptr::null(),
+                                      BinaryenIndex(0),
+                                      BinaryenInt32()))
+                }
+                "abort" | "unreachable" => {
+                    statements.push(BinaryenUnreachable(module));
+                    None
+                }
+                "ctpop" | "ctlz" | "cttz" | "bswap" => {
+                    let arg = self.trans_operand(&args[0]);
+                    Some(self.emit_bit_intrinsic(&name, substs.type_at(0), arg))
+                }
+                "sqrtf32" | "sqrtf64" | "floorf32" | "floorf64" => {
+                    let op = match &*name {
+                        "sqrtf32" => BinaryenSqrtFloat32(),
+                        "sqrtf64" => BinaryenSqrtFloat64(),
+                        "floorf32" => BinaryenFloorFloat32(),
+                        _ => BinaryenFloorFloat64(),
+                    };
+                    let arg = self.trans_operand(&args[0]);
+                    Some(BinaryenUnary(module, op, arg))
+                }
+                "add_with_overflow" | "sub_with_overflow" | "mul_with_overflow" => {
+                    let op = match &*name {
+                        "add_with_overflow" => BinOp::Add,
+                        "sub_with_overflow" => BinOp::Sub,
+                        _ => BinOp::Mul,
+                    };
+                    if let Some((ref lvalue, _)) = *destination {
+                        if let Some(dest) = self.trans_lval(lvalue) {
+                            let dest_ty = lvalue.ty(self.mir, *self.tcx).to_ty(*self.tcx);
+                            self.trans_checked_binary_op(op,
+                                                         &args[0],
+                                                         &args[1],
+                                                         dest,
+                                                         dest_ty,
+                                                         statements);
+                        }
                     }
-                    Literal::Promoted { .. } => panic!("unimplemented Promoted Literal: {:?}", c),
-                    _ => panic!("unimplemented Constant Literal {:?}", c),
+                    None
+                }
+                "volatile_load" => {
+                    let ptr = self.trans_operand(&args[0]);
+                    Some(self.emit_load(substs.type_at(0), ptr, 0))
+                }
+                "volatile_store" => {
+                    let ptr = self.trans_operand(&args[0]);
+                    let val = self.trans_operand(&args[1]);
+                    statements.push(self.emit_store(substs.type_at(0), ptr, 0, val));
+                    None
+                }
+                _ => {
+                    self.unsupported(UnsupportedKind::Intrinsic, format!("intrinsic {}", name));
+                    None
                 }
             }
+        };
+
+        if let Some(value) = value {
+            let dest = match *destination {
+                Some((ref lvalue, _)) => {
+                    self.trans_lval(lvalue)
+                        .map(|dest| (dest, lvalue.ty(self.mir, *self.tcx).to_ty(*self.tcx)))
+                }
+                None => None,
+            };
+            let statement = match dest {
+                Some((dest, dest_ty)) => {
+                    debug!("emitting assignment of intrinsic {} result to {:?}", name, dest);
+                    self.emit_assign(dest, dest_ty, value)
+                }
+                None => unsafe { BinaryenDrop(module, value) },
+            };
+            statements.push(statement);
         }
+
+        true
     }
 
-    #[inline]
-    fn type_size(&self, ty: Ty<'tcx>) -> usize {
-        let substs = Substs::empty(*self.tcx);
-        self.type_size_with_substs(ty, substs)
+    fn emit_transmute(&mut self,
+                      value: BinaryenExpressionRef,
+                      src_ty: Ty<'tcx>,
+                      dest_ty: Ty<'tcx>)
+                      -> BinaryenExpressionRef {
+        match (self.is_immediate(src_ty), self.is_immediate(dest_ty)) {
+            (true, true) => {
+                let (src, dest) = (rust_ty_to_binaryen(src_ty), rust_ty_to_binaryen(dest_ty));
+                if src == dest {
+                    return value;
+                }
+                let op = if src == BinaryenFloat32() && dest == BinaryenInt32() {
+                    BinaryenReinterpretFloat32()
+                } else if src == BinaryenFloat64() && dest == BinaryenInt64() {
+                    BinaryenReinterpretFloat64()
+                } else if src == BinaryenInt32() && dest == BinaryenFloat32() {
+                    BinaryenReinterpretInt32()
+                } else if src == BinaryenInt64() && dest == BinaryenFloat64() {
+                    BinaryenReinterpretInt64()
+                } else {
+                    return self.unsupported(UnsupportedKind::Intrinsic,
+                                            format!("transmute from {:?} to {:?}",
+                                                    src_ty,
+                                                    dest_ty));
+                };
+                unsafe { BinaryenUnary(self.func.module.module, op, value) }
+            }
+            // aggregates are represented by a pointer to their linear memory location
+            (false, false) => value,
+            (false, true) => self.emit_load(dest_ty, value, 0),
+            // stored in the destination's memory by `emit_transmute_to_aggregate`
+            (true, false) => {
+                let message = format!("transmute from immediate {:?} to aggregate {:?}",
+                                      src_ty,
+                                      dest_ty);
+                self.unsupported(UnsupportedKind::Intrinsic, message)
+            }
+        }
     }
 
-    // Imported from miri
-    #[inline]
-    fn type_size_with_substs(&self, ty: Ty<'tcx>, substs: &'tcx Substs<'tcx>) -> usize {
-        self.type_layout_with_substs(ty, substs).size(&self.tcx.data_layout).bytes() as usize
+    /// Transmutes an immediate into an aggregate, by storing it in the aggregate's memory: its
+    /// frame slot when the destination is a local.
+    fn emit_transmute_to_aggregate(&mut self,
+                                   value: BinaryenExpressionRef,
+                                   src_ty: Ty<'tcx>,
+                                   destination: &Option<(Lvalue<'tcx>, BasicBlock)>,
+                                   statements: &mut Vec<BinaryenExpressionRef>) {
+        let dest = match *destination {
+            Some((ref lvalue, _)) => self.trans_lval(lvalue),
+            None => None,
+        };
+        let dest = match dest {
+            Some(dest) => dest,
+            None => {
+                statements.push(unsafe { BinaryenDrop(self.func.module.module, value) });
+                return;
+            }
+        };
+
+        let offset = match dest.offset {
+            Some(offset) => offset,
+            None => {
+                debug!("allocating the transmuted aggregate in linear memory to SetLocal({})",
+                       dest.index.0);
+                statements.push(self.emit_frame_slot(dest.index));
+                0
+            }
+        };
+        let ptr = unsafe { BinaryenGetLocal(self.func.module.module, dest.index, BinaryenInt32()) };
+        statements.push(self.emit_store(src_ty, ptr, offset, value));
     }
 
-    #[inline]
-    fn type_layout(&self, ty: Ty<'tcx>) -> &'tcx Layout {
-        let substs = Substs::empty(*self.tcx);
-        self.type_layout_with_substs(ty, substs)
+    /// Multiplies an element count by the size of `ty`, giving a length in bytes.
+    fn emit_byte_count(&mut self, ty: Ty<'tcx>, count: &Operand<'tcx>) -> BinaryenExpressionRef {
+        let size = self.type_size(ty) as i32;
+        let count = self.trans_operand(count);
+        if size == 1 {
+            return count;
+        }
+        unsafe {
+            let size = BinaryenConst(self.func.module.module, BinaryenLiteralInt32(size));
+            BinaryenBinary(self.func.module.module, BinaryenMulInt32(), count, size)
+        }
     }
 
-    // Imported from miri and slightly modified to adapt to our monomorphize api
-    fn type_layout_with_substs(&self, ty: Ty<'tcx>, substs: &'tcx Substs<'tcx>) -> &'tcx Layout {
-        // TODO(solson): Is this inefficient? Needs investigation.
-        let ty = monomorphize::apply_ty_substs(self.tcx, substs, ty);
+    fn emit_bit_intrinsic(&mut self,
+                          name: &str,
+                          ty: Ty<'tcx>,
+                          value: BinaryenExpressionRef)
+                          -> BinaryenExpressionRef {
+        let bits = self.type_size(ty) as u32 * 8;
+        if name == "bswap" {
+            return self.emit_bswap(value, bits / 8);
+        }
 
-        self.tcx.infer_ctxt(None, None, Reveal::All).enter(|infcx| {
-            // TODO(solson): Report this error properly.
-            ty.layout(&infcx).unwrap()
-        })
+        let module = self.func.module.module;
+        unsafe {
+            if bits == 64 {
+                let op = match name {
+                    "ctpop" => BinaryenPopcntInt64(),
+                    "ctlz" => BinaryenClzInt64(),
+                    _ => BinaryenCtzInt64(),
+                };
+                return BinaryenUnary(module, op, value);
+            }
+
+            // Integers narrower than 32 bits still live in an i32, so mask off the high bits
+            // and correct the counts for the narrower width.
+            let konst = |x: i32| BinaryenConst(module, BinaryenLiteralInt32(x));
+            let mask = if bits == 32 { -1 } else { (1 << bits) - 1 };
+            match name {
+                "ctpop" => {
+                    let value = BinaryenBinary(module, BinaryenAndInt32(), value, konst(mask));
+                    BinaryenUnary(module, BinaryenPopcntInt32(), value)
+                }
+                "ctlz" => {
+                    let value = BinaryenBinary(module, BinaryenAndInt32(), value, konst(mask));
+                    let count = BinaryenUnary(module, BinaryenClzInt32(), value);
+                    BinaryenBinary(module, BinaryenSubInt32(), count, konst(32 - bits as i32))
+                }
+                _ => {
+                    // setting the bit just above the width caps the count at `bits`
+                    let guard = if bits == 32 { 0 } else { 1 << bits };
+                    let value = BinaryenBinary(module, BinaryenOrInt32(), value, konst(guard));
+                    BinaryenUnary(module, BinaryenCtzInt32(), value)
+                }
+            }
+        }
     }
 
-    fn trans_fn_name_direct(&mut self,
-                            operand: &Operand<'tcx>)
-                            -> Option<(*const c_char, BinaryenType, BinaryenCallKind)> {
-        match *operand {
-            Operand::Constant(ref c) => {
-                match c.literal {
-                    Literal::Item { def_id, ref substs } => {
-                        let ty = self.tcx.lookup_item_type(def_id).ty;
-                        if ty.is_fn() {
-                            assert!(def_id.is_local());
-                            let sig = ty.fn_sig().skip_binder();
+    fn emit_bswap(&mut self, value: BinaryenExpressionRef, bytes: u32) -> BinaryenExpressionRef {
+        if bytes == 1 {
+            return value;
+        }
 
-                            let mut fn_did = def_id;
-                            let fn_name = self.tcx.item_path_str(fn_did);
-                            let fn_sig;
-                            let mut call_kind = BinaryenCallKind::Direct;
-
-                            match fn_name.as_ref() {
-                                "wasm::::print_i32" |
-                                "wasm::::_print_i32" => {
-                                    // extern wasm functions
-                                    fn_sig = sig.clone();
-                                    call_kind = BinaryenCallKind::Import;
-                                    self.import_wasm_extern(fn_did, sig);
-                                }
-                                _ => {
-                                    let is_trait_method = self.tcx.trait_of_item(fn_did).is_some();
-
-                                    let (substs, sig) = if !is_trait_method {
-                                        (*substs, sig)
-                                    } else {
-                                        let (resolved_def_id, resolved_substs) =
-                                            traits::resolve_trait_method(self.tcx, fn_did, substs);
-                                        let ty = self.tcx.lookup_item_type(resolved_def_id).ty;
-                                        // TODO: investigate rustc trans use of
-                                        // liberate_bound_regions or similar here
-                                        let sig = ty.fn_sig().skip_binder();
-
-                                        fn_did = resolved_def_id;
-                                        (resolved_substs, sig)
-                                    };
-
-                                    let mir = &self.mir_map.map[&fn_did];
-
-                                    fn_sig =
-                                        monomorphize::apply_param_substs(self.tcx, substs, sig);
-
-                                    // mark the fn defid seen to not have translated twice
-                                    // TODO: verify this more thoroughly, works for our limited
-                                    // tests right now
-                                    if *sig != fn_sig {
-                                        let fn_name = sanitize_symbol(&self.tcx
-                                            .item_path_str(fn_did));
-                                        let fn_name = CString::new(fn_name).expect("");
-                                        self.fun_names.insert((fn_did, sig.clone()), fn_name);
-                                    }
+        let wide = bytes == 8;
+        let (repr, wasm_ty) = if wide {
+            (builder::ReprType::Int64, BinaryenInt64())
+        } else {
+            (builder::ReprType::Int32, BinaryenInt32())
+        };
+        let tmp: BinaryenIndex = self.func.create_local(repr).index().into();
 
-                                    // This simple check is also done in trans() but doing it here
-                                    // helps have a clearer debug log
-                                    if !self.fun_names.contains_key(&(fn_did, fn_sig.clone())) {
-                                        let mut ctxt = BinaryenFnCtxt {
-                                            tcx: self.tcx,
-                                            mir_map: self.mir_map,
-                                            mir: mir,
-                                            did: fn_did,
-                                            sig: &fn_sig,
-                                            func: self.func.module.create_func(),
-                                            entry_fn: self.entry_fn,
-                                            fun_types: &mut self.fun_types,
-                                            fun_names: &mut self.fun_names,
-                                            c_strings: &mut self.c_strings,
-                                            checked_op_local: None,
-                                            var_map: Vec::new(),
-                                            temp_map: Vec::new(),
-                                            ret_var: None,
-                                        };
+        let module = self.func.module.module;
+        unsafe {
+            let konst = |x: u32| if wide {
+                BinaryenConst(module, BinaryenLiteralInt64(x as i64))
+            } else {
+                BinaryenConst(module, BinaryenLiteralInt32(x as i32))
+            };
+            let (and, or, shl, shr) = if wide {
+                (BinaryenAndInt64(), BinaryenOrInt64(), BinaryenShlInt64(), BinaryenShrUInt64())
+            } else {
+                (BinaryenAndInt32(), BinaryenOrInt32(), BinaryenShlInt32(), BinaryenShrUInt32())
+            };
 
-                                        debug!("translating monomorphized fn {:?}",
-                                               self.tcx.item_path_str(fn_did));
-                                        ctxt.trans();
-                                        debug!("done translating monomorphized {:?}, continuing \
-                                                translation of fn {:?}",
-                                               self.tcx.item_path_str(fn_did),
-                                               self.tcx.item_path_str(self.did));
-                                    }
-                                }
-                            }
+            let mut result = None;
+            for i in 0..bytes {
+                // the leftmost operand is evaluated first, and saves the value for the others
+                let src = if i == 0 {
+                    BinaryenTeeLocal(module, tmp, value)
+                } else {
+                    BinaryenGetLocal(module, tmp, wasm_ty)
+                };
+                let byte = BinaryenBinary(module, shr, src, konst(8 * i));
+                let byte = BinaryenBinary(module, and, byte, konst(0xff));
+                let byte = BinaryenBinary(module, shl, byte, konst(8 * (bytes - 1 - i)));
+                result = Some(match result {
+                    None => byte,
+                    Some(acc) => BinaryenBinary(module, or, acc, byte),
+                });
+            }
+            result.unwrap()
+        }
+    }
 
-                            let ret_ty = if !fn_sig.output.is_nil() {
-                                rust_ty_to_binaryen(fn_sig.output)
-                            } else {
-                                BinaryenNone()
-                            };
+    fn is_immediate(&self, ty: Ty<'tcx>) -> bool {
+        match *self.type_layout(ty) {
+            Layout::Scalar { .. } |
+            Layout::CEnum { .. } |
+            Layout::RawNullablePointer { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the name of a runtime support fn, emitting it into the module on first use.
+    fn runtime_fn(&mut self, runtime_fn: RuntimeFn) -> *const c_char {
+        if let Some(name) = self.runtime_fns.get(&runtime_fn) {
+            return name.as_ptr();
+        }
+
+        let name = match runtime_fn {
+            RuntimeFn::Memmove => "__wasm_memmove",
+            RuntimeFn::Memset => "__wasm_memset",
+            RuntimeFn::Panic => "rust_panic",
+            RuntimeFn::StackOverflow => "__rust_stack_overflow",
+            RuntimeFn::Unsupported => "__unsupported",
+            RuntimeFn::HeapBase => "__rust_heap_base",
+        };
+        debug!("emitting runtime support fn {}", name);
+        let name = CString::new(name).expect("");
+        let name_ptr = name.as_ptr();
+        self.runtime_fns.insert(runtime_fn, name);
+
+        let body = match runtime_fn {
+            RuntimeFn::Memmove => self.emit_memmove_body(),
+            RuntimeFn::Memset => self.emit_memset_body(),
+            RuntimeFn::Panic | RuntimeFn::StackOverflow | RuntimeFn::Unsupported => {
+                // env.rust_panic(msg_ptr, msg_len, file_ptr, file_len, line),
+                // env.__rust_stack_overflow(), or env.__unsupported(fn_id)
+                let env = self.c_str("env");
+                let param_count = match runtime_fn {
+                    RuntimeFn::Panic => 5,
+                    RuntimeFn::Unsupported => 1,
+                    _ => 0,
+                };
+                unsafe {
+                    let params = vec![BinaryenInt32(); param_count];
+                    let fn_ty = BinaryenAddFunctionType(self.func.module.module,
+                                                        name_ptr,
+                                                        BinaryenNone(),
+                                                        params.as_ptr(),
+                                                        BinaryenIndex(params.len() as _));
+                    BinaryenAddImport(self.func.module.module, name_ptr, env, name_ptr, fn_ty);
+                }
+                return name_ptr;
+            }
+            RuntimeFn::HeapBase => return name_ptr,
+        };
+
+        unsafe {
+            // (dst, src or byte value, len), plus a loop counter local
+            let params = [BinaryenInt32(), BinaryenInt32(), BinaryenInt32()];
+            let fn_ty = BinaryenAddFunctionType(self.func.module.module,
+                                                name_ptr,
+                                                BinaryenNone(),
+                                                params.as_ptr(),
+                                                BinaryenIndex(params.len() as _));
+            let locals = [BinaryenInt32()];
+            BinaryenAddFunction(self.func.module.module,
+                                name_ptr,
+                                fn_ty,
+                                locals.as_ptr(),
+                                BinaryenIndex(locals.len() as _),
+                                body);
+        }
+
+        name_ptr
+    }
+
+    /// The name the fn is exported under, if it's `#[no_mangle]`, `#[export_name = "..."]` or an
+    /// `extern "C"` fn reachable from other crates, like rustc's exported symbols, rather than
+    /// just `pub`. Generic fns are never exported.
+    fn export_name(&self) -> Option<String> {
+        let generics = &self.tcx.lookup_item_type(self.did).generics;
+        if generics.types.len() + generics.parent_types as usize > 0 {
+            return None;
+        }
+
+        let attrs = self.tcx.get_attrs(self.did);
+        if let Some(name) = attr::first_attr_value_str_by_name(&attrs, "export_name") {
+            return Some(name.to_string());
+        }
+
+        let is_reachable_extern = match self.tcx.lookup_item_type(self.did).ty.sty {
+            ty::TyFnDef(_, _, fn_ty) if fn_ty.abi == Abi::C => {
+                let nid = self.tcx.map.as_local_node_id(self.did).expect("");
+                self.access_levels.is_reachable(nid)
+            }
+            _ => false,
+        };
+        if attr::contains_name(&attrs, "no_mangle") || is_reachable_extern {
+            Some(self.tcx.item_name(self.did).as_str().to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Which panic entry fn, if any, is being translated.
+    fn panic_entry(&self) -> Option<PanicEntry> {
+        let lang_items = &self.tcx.lang_items;
+        if Some(self.did) == lang_items.panic_fn() {
+            Some(PanicEntry::Panic)
+        } else if Some(self.did) == lang_items.panic_fmt() {
+            Some(PanicEntry::PanicFmt)
+        } else if self.is_begin_panic(self.did) {
+            Some(PanicEntry::BeginPanic)
+        } else {
+            None
+        }
+    }
+
+    /// `begin_panic` is not a lang item: it's found by its def path in `std`, which, unlike its
+    /// item path, doesn't depend on the crate being translated.
+    fn is_begin_panic(&self, did: DefId) -> bool {
+        if &*self.tcx.crate_name(did.krate) != "std" {
+            return false;
+        }
+        let path: Vec<_> = self.tcx
+            .def_path(did)
+            .data
+            .iter()
+            .map(|elem| elem.data.as_interned_str().to_string())
+            .collect();
+        path == ["panicking", "begin_panic"]
+    }
+
+    /// Passes the message, file and line of the panic to the host, and traps or starts
+    /// unwinding. The `fmt::Arguments` of `panic_fmt` are not formatted: its message is the
+    /// format string up to the first argument.
+    fn trans_panic_entry(&mut self, panic_entry: PanicEntry) -> BinaryenExpressionRef {
+        let module = self.func.module.module;
+        let arg_count = self.sig.inputs.len();
+        let str_msg = match self.sig.inputs.first().map(|ty| &ty.sty) {
+            Some(&ty::TyRef(_, ref mt)) => mt.ty.is_str(),
+            _ => false,
+        };
+        unsafe {
+            let arg = |index: usize| BinaryenGetLocal(module, index.into(), BinaryenInt32());
+            let load = |ptr, offset| BinaryenLoad(module, 4, 0, offset, 0, BinaryenInt32(), ptr);
+            let zero = || BinaryenConst(module, BinaryenLiteralInt32(0));
+            // the data pointer and length of a &str, at `offset` in what the arg points to
+            let str_at = |index, offset| (load(arg(index), offset), load(arg(index), offset + 4));
+
+            let (msg, file, line) = match panic_entry {
+                PanicEntry::Panic if arg_count == 1 => {
+                    (str_at(0, 0), str_at(0, 8), load(arg(0), 16))
+                }
+                PanicEntry::PanicFmt if arg_count == 3 => {
+                    // the first of the `pieces: &[&str]` of the arguments
+                    let pieces = || load(arg(0), 0);
+                    let pieces_len = load(arg(0), 4);
+                    let msg_len = BinaryenSelect(module, pieces_len, load(pieces(), 4), zero());
+                    ((load(pieces(), 0), msg_len), str_at(1, 0), arg(2))
+                }
+                PanicEntry::BeginPanic if arg_count == 2 && str_msg => {
+                    (str_at(0, 0), str_at(1, 0), load(arg(1), 8))
+                }
+                _ => {
+                    debug!("unknown signature for panic entry fn {:?}, reporting no message",
+                           self.sig);
+                    ((zero(), zero()), (zero(), zero()), zero())
+                }
+            };
+
+            let statements = [self.emit_panic_report(msg, file, line), self.emit_panic_end()];
+            BinaryenBlock(module,
+                          ptr::null(),
+                          statements.as_ptr(),
+                          BinaryenIndex(statements.len() as _))
+        }
+    }
+
+    /// Calls the `env.rust_panic` host import with the panic message, file and line.
+    fn emit_panic_report(&mut self,
+                         (msg_ptr, msg_len): (BinaryenExpressionRef, BinaryenExpressionRef),
+                         (file_ptr, file_len): (BinaryenExpressionRef, BinaryenExpressionRef),
+                         line: BinaryenExpressionRef)
+                         -> BinaryenExpressionRef {
+        let rust_panic = self.runtime_fn(RuntimeFn::Panic);
+        let args = [msg_ptr, msg_len, file_ptr, file_len, line];
+        unsafe {
+            BinaryenCallImport(self.func.module.module,
+                               rust_panic,
+                               args.as_ptr(),
+                               BinaryenIndex(args.len() as _),
+                               BinaryenNone())
+        }
+    }
+
+    /// Reports a panic whose message is known at compile time, like failed asserts, with the
+    /// file and line of `span`.
+    fn emit_static_panic_report(&mut self, msg: &str, span: Span) -> BinaryenExpressionRef {
+        let loc = self.tcx.sess.codemap().lookup_char_pos(span.lo);
+        let msg_ptr = self.alloc_str(msg);
+        let file_ptr = self.alloc_str(&loc.file.name);
+        let module = self.func.module.module;
+        let int32 = |value: usize| unsafe {
+            BinaryenConst(module, BinaryenLiteralInt32(value as i32))
+        };
+        self.emit_panic_report((int32(msg_ptr as usize), int32(msg.len())),
+                               (int32(file_ptr as usize), int32(loc.file.name.len())),
+                               int32(loc.line))
+    }
+
+    /// What follows a reported panic: a trap, or setting the panic flag when unwinding.
+    fn emit_panic_end(&self) -> BinaryenExpressionRef {
+        match self.panic_strategy {
+            PanicStrategy::Abort => unsafe { BinaryenUnreachable(self.func.module.module) },
+            PanicStrategy::Unwind => self.emit_set_panic_flag(true),
+        }
+    }
+
+    /// Records a construct which can't be translated yet, at the current statement or
+    /// terminator, and returns an `Unreachable` standing in for its translation. Translation goes
+    /// on, to find every unsupported construct of the crate in one run, but the fn is not emitted.
+    fn unsupported(&mut self, kind: UnsupportedKind, message: String) -> BinaryenExpressionRef {
+        self.record_unsupported(kind, message);
+        unsafe { BinaryenUnreachable(self.func.module.module) }
+    }
+
+    /// Like `unsupported`, for the helpers only borrowing the fn ctxt, like type layouts.
+    fn record_unsupported(&self, kind: UnsupportedKind, message: String) {
+        debug!("unsupported {:?}: {}", kind, message);
+        self.unsupported.borrow_mut().push(Unsupported {
+            kind: kind,
+            span: self.span,
+            message: message,
+        });
+    }
+
+    /// Like `unsupported`, for lvalues: the frame base local stands in for the lvalue.
+    fn unsupported_lval(&mut self, message: String) -> Option<BinaryenLvalue> {
+        self.unsupported(UnsupportedKind::Lvalue, message);
+        let base_local = self.frame().base_local;
+        Some(BinaryenLvalue::new(base_local, None, LvalueExtra::None))
+    }
+
+    /// Reports the unsupported constructs of the fn as errors, or as warnings when the fn is
+    /// replaced by the stub with id `stub_id`.
+    fn report_unsupported(&self, stub_id: Option<usize>) {
+        for unsupported in self.unsupported.borrow().iter() {
+            let code = unsupported.kind.code();
+            match stub_id {
+                Some(fn_id) => {
+                    let message = format!("unsupported {} in `{}`, which traps when called (fn \
+                                           id {})",
+                                          unsupported.message,
+                                          self.tcx.item_path_str(self.did),
+                                          fn_id);
+                    self.tcx.sess.span_warn_with_code(unsupported.span, &message, code);
+                }
+                None => {
+                    let message = format!("unsupported {}", unsupported.message);
+                    self.tcx.sess.span_err_with_code(unsupported.span, &message, code);
+                }
+            }
+        }
+    }
+
+    /// The body replacing a fn with unsupported constructs: `env.__unsupported(fn_id)`, then a
+    /// trap.
+    fn emit_unsupported_stub(&mut self, fn_id: usize) -> BinaryenExpressionRef {
+        debug!("emitting unsupported stub {} for fn {:?}",
+               fn_id,
+               self.tcx.item_path_str(self.did));
+        let handler = self.runtime_fn(RuntimeFn::Unsupported);
+        let module = self.func.module.module;
+        unsafe {
+            let operands = [BinaryenConst(module, BinaryenLiteralInt32(fn_id as i32))];
+            let statements = [BinaryenCallImport(module,
+                                                 handler,
+                                                 operands.as_ptr(),
+                                                 BinaryenIndex(operands.len() as _),
+                                                 BinaryenNone()),
+                              BinaryenUnreachable(module)];
+            BinaryenBlock(module,
+                          ptr::null(),
+                          statements.as_ptr(),
+                          BinaryenIndex(statements.len() as _))
+        }
+    }
+
+    fn c_str(&mut self, s: &str) -> *const c_char {
+        let s = CString::new(s).expect("");
+        let s_ptr = s.as_ptr();
+        self.c_strings.push(s);
+        s_ptr
+    }
+
+    // A byte by byte memmove, copying backwards when the destination is above the source so
+    // that overlapping ranges are handled.
+    fn emit_memmove_body(&mut self) -> BinaryenExpressionRef {
+        let (dst, src, len, i) =
+            (BinaryenIndex(0), BinaryenIndex(1), BinaryenIndex(2), BinaryenIndex(3));
+        let fwd_loop = self.c_str("memmove_fwd");
+        let fwd_done = self.c_str("memmove_fwd_done");
+        let bwd_loop = self.c_str("memmove_bwd");
+        let bwd_done = self.c_str("memmove_bwd_done");
+
+        let module = self.func.module.module;
+        unsafe {
+            let none = BinaryenExpressionRef(ptr::null_mut());
+            let get = |index| BinaryenGetLocal(module, index, BinaryenInt32());
+            let one = || BinaryenConst(module, BinaryenLiteralInt32(1));
+            let copy_byte = || {
+                let from = BinaryenBinary(module, BinaryenAddInt32(), get(src), get(i));
+                let to = BinaryenBinary(module, BinaryenAddInt32(), get(dst), get(i));
+                let byte = BinaryenLoad(module, 1, 0, 0, 0, BinaryenInt32(), from);
+                BinaryenStore(module, 1, 0, 0, to, byte, BinaryenInt32())
+            };
+
+            // locals start zeroed, so the forward copy starts at i = 0
+            let forward = [BinaryenBreak(module,
+                                         fwd_done,
+                                         BinaryenBinary(module,
+                                                        BinaryenGeUInt32(),
+                                                        get(i),
+                                                        get(len)),
+                                         none),
+                           copy_byte(),
+                           BinaryenSetLocal(module,
+                                            i,
+                                            BinaryenBinary(module,
+                                                           BinaryenAddInt32(),
+                                                           get(i),
+                                                           one())),
+                           BinaryenBreak(module, fwd_loop, none, none)];
+            let forward = BinaryenBlock(module,
+                                        ptr::null(),
+                                        forward.as_ptr(),
+                                        BinaryenIndex(forward.len() as _));
+            let forward = [BinaryenLoop(module, fwd_loop, forward)];
+            let forward = BinaryenBlock(module,
+                                        fwd_done,
+                                        forward.as_ptr(),
+                                        BinaryenIndex(forward.len() as _));
+
+            let backward = [BinaryenBreak(module,
+                                          bwd_done,
+                                          BinaryenUnary(module, BinaryenEqZInt32(), get(i)),
+                                          none),
+                            BinaryenSetLocal(module,
+                                             i,
+                                             BinaryenBinary(module,
+                                                            BinaryenSubInt32(),
+                                                            get(i),
+                                                            one())),
+                            copy_byte(),
+                            BinaryenBreak(module, bwd_loop, none, none)];
+            let backward = BinaryenBlock(module,
+                                         ptr::null(),
+                                         backward.as_ptr(),
+                                         BinaryenIndex(backward.len() as _));
+            let backward = [BinaryenSetLocal(module, i, get(len)),
+                            BinaryenLoop(module, bwd_loop, backward)];
+            let backward = BinaryenBlock(module,
+                                         bwd_done,
+                                         backward.as_ptr(),
+                                         BinaryenIndex(backward.len() as _));
+
+            let is_forward = BinaryenBinary(module, BinaryenLeUInt32(), get(dst), get(src));
+            BinaryenIf(module, is_forward, forward, backward)
+        }
+    }
 
-                            Some((self.fun_names[&(fn_did, fn_sig)].as_ptr(), ret_ty, call_kind))
-                        } else {
-                            panic!("unimplemented ty {:?} for {:?}", ty, def_id);
-                        }
-                    }
-                    _ => panic!("{:?}", c),
-                }
-            }
-            _ => panic!(),
+    // A byte by byte memset
+    fn emit_memset_body(&mut self) -> BinaryenExpressionRef {
+        let (dst, value, len, i) =
+            (BinaryenIndex(0), BinaryenIndex(1), BinaryenIndex(2), BinaryenIndex(3));
+        let fill_loop = self.c_str("memset_fill");
+        let fill_done = self.c_str("memset_done");
+
+        let module = self.func.module.module;
+        unsafe {
+            let none = BinaryenExpressionRef(ptr::null_mut());
+            let get = |index| BinaryenGetLocal(module, index, BinaryenInt32());
+
+            let fill = [BinaryenBreak(module,
+                                      fill_done,
+                                      BinaryenBinary(module, BinaryenGeUInt32(), get(i), get(len)),
+                                      none),
+                        BinaryenStore(module,
+                                      1,
+                                      0,
+                                      0,
+                                      BinaryenBinary(module, BinaryenAddInt32(), get(dst), get(i)),
+                                      get(value),
+                                      BinaryenInt32()),
+                        BinaryenSetLocal(module,
+                                         i,
+                                         BinaryenBinary(module,
+                                                        BinaryenAddInt32(),
+                                                        get(i),
+                                                        BinaryenConst(module,
+                                                                      BinaryenLiteralInt32(1)))),
+                        BinaryenBreak(module, fill_loop, none, none)];
+            let fill = BinaryenBlock(module,
+                                     ptr::null(),
+                                     fill.as_ptr(),
+                                     BinaryenIndex(fill.len() as _));
+            let fill = [BinaryenLoop(module, fill_loop, fill)];
+            BinaryenBlock(module, fill_done, fill.as_ptr(), BinaryenIndex(fill.len() as _))
         }
     }
 
@@ -1613,28 +4238,13 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
 
             let mut statements = vec![];
 
-            // set-up memory and stack
-            // FIXME: decide how memory's going to work, the stack pointer address,
-            //        track its initial size, etc and extract that into its own abstraction
-            //     -> temporarily, just ask for one 64K page
-            BinaryenSetMemory(self.func.module.module,
-                              BinaryenIndex(1),
-                              BinaryenIndex(1),
-                              ptr::null(),
-                              ptr::null(),
-                              ptr::null(),
-                              ptr::null(),
-                              BinaryenIndex(0));
-
-            let stack_top = BinaryenConst(self.func.module.module, BinaryenLiteralInt32(0xFFFF));
-            let stack_init = BinaryenStore(self.func.module.module,
-                                           4,
-                                           0,
-                                           0,
-                                           self.emit_sp(),
-                                           stack_top,
-                                           BinaryenInt32());
-            statements.push(stack_init);
+            // set-up the stack, once the memory layout is known
+            let runtime_init = self.c_str(RUNTIME_INIT_NAME);
+            statements.push(BinaryenCall(self.func.module.module,
+                                         runtime_init,
+                                         ptr::null(),
+                                         BinaryenIndex(0),
+                                         BinaryenNone()));
 
             // call start_fn(0, 0) or main()
             let entry_fn_call;
@@ -1659,6 +4269,15 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
 
             statements.push(entry_fn_call);
 
+            // a panic unwinding out of the entry fn traps
+            if self.panic_strategy == PanicStrategy::Unwind {
+                let uncaught = BinaryenIf(self.func.module.module,
+                                          self.emit_panic_flag(),
+                                          BinaryenUnreachable(self.func.module.module),
+                                          BinaryenExpressionRef(ptr::null_mut()));
+                statements.push(uncaught);
+            }
+
             let body = BinaryenBlock(self.func.module.module,
                                      ptr::null(),
                                      statements.as_ptr(),
@@ -1676,43 +4295,84 @@ impl<'v, 'tcx: 'v, 'module: 'v> BinaryenFnCtxt<'v, 'tcx, 'module> {
         }
     }
 
+    fn is_foreign_fn(&self, did: DefId) -> bool {
+        match self.tcx.map.as_local_node_id(did).and_then(|id| self.tcx.map.find(id)) {
+            Some(hir_map::NodeForeignItem(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Imports the foreign fn `did` from the host. The wasm module it's imported from is the
+    /// `#[wasm_import_module = "..."]`, or `#[link(name = "...")]`, of its `extern` block,
+    /// defaulting to "env". The imported name is its `#[link_name]`, or its own name.
     fn import_wasm_extern(&mut self, did: DefId, sig: &ty::FnSig<'tcx>) {
         if self.fun_names.contains_key(&(did, sig.clone())) {
             return;
         }
 
-        // import print i32
-        let print_i32_name = CString::new("print_i32").expect("");
-        let print_i32 = print_i32_name.as_ptr();
-        self.fun_names.insert((did, sig.clone()), print_i32_name.clone());
-        self.c_strings.push(print_i32_name);
-
-        let spectest_module_name = CString::new("spectest").expect("");
-        let spectest_module = spectest_module_name.as_ptr();
-        self.c_strings.push(spectest_module_name);
+        let id = self.tcx.map.as_local_node_id(did).expect("");
+        let foreign_item = self.tcx.map.expect_foreign_item(id);
+        let foreign_mod = self.tcx.map.expect_item(self.tcx.map.get_parent(id));
 
-        let print_fn_name = CString::new("print").expect("");
-        let print_fn = print_fn_name.as_ptr();
-        self.c_strings.push(print_fn_name);
-
-        let print_i32_args = [BinaryenInt32()];
+        let module_name = match attr::first_attr_value_str_by_name(&foreign_mod.attrs,
+                                                                   "wasm_import_module") {
+            Some(name) => name.to_string(),
+            None => {
+                foreign_mod.attrs
+                    .iter()
+                    .filter(|attr| attr.check_name("link"))
+                    .filter_map(|attr| attr.meta_item_list())
+                    .flat_map(|items| items.iter())
+                    .find(|item| item.check_name("name"))
+                    .and_then(|item| item.value_str())
+                    .map_or("env".to_string(), |name| name.to_string())
+            }
+        };
+        let base_name = match attr::first_attr_value_str_by_name(&foreign_item.attrs,
+                                                                 "link_name") {
+            Some(name) => name.to_string(),
+            None => foreign_item.name.as_str().to_string(),
+        };
+        let import_name = sanitize_symbol(&self.tcx.item_path_str(did));
+        debug!("importing extern fn {} from host {}::{}",
+               import_name,
+               module_name,
+               base_name);
+
+        let import_name = CString::new(import_name).expect("");
+        let import_name_ptr = import_name.as_ptr();
+        self.fun_names.insert((did, sig.clone()), import_name);
+        let module_name = self.c_str(&module_name);
+        let base_name = self.c_str(&base_name);
+
+        // zero-sized args and returns are not passed, like in calls to translated fns
+        let params: Vec<_> = sig.inputs
+            .iter()
+            .filter(|ty| !self.is_zst(ty))
+            .map(|ty| rust_ty_to_binaryen(ty))
+            .collect();
+        let ret_ty = if self.is_zst(sig.output) {
+            BinaryenNone()
+        } else {
+            rust_ty_to_binaryen(sig.output)
+        };
         unsafe {
-            let print_i32_ty = BinaryenAddFunctionType(self.func.module.module,
-                                                       print_i32,
-                                                       BinaryenNone(),
-                                                       print_i32_args.as_ptr(),
-                                                       BinaryenIndex(1));
+            let fn_ty = BinaryenAddFunctionType(self.func.module.module,
+                                                import_name_ptr,
+                                                ret_ty,
+                                                params.as_ptr(),
+                                                BinaryenIndex(params.len() as _));
             BinaryenAddImport(self.func.module.module,
-                              print_i32,
-                              spectest_module,
-                              print_fn,
-                              print_i32_ty);
+                              import_name_ptr,
+                              module_name,
+                              base_name,
+                              fn_ty);
         }
     }
 }
 
+/// The wasm type of a non zero-sized `t`, see `BinaryenFnCtxt::is_zst`.
 fn rust_ty_to_binaryen<'tcx>(t: Ty<'tcx>) -> BinaryenType {
-    // FIXME zero-sized-types
     match t.sty {
         ty::TyFloat(FloatTy::F32) => BinaryenFloat32(),
         ty::TyFloat(FloatTy::F64) => BinaryenFloat64(),
@@ -1722,6 +4382,306 @@ fn rust_ty_to_binaryen<'tcx>(t: Ty<'tcx>) -> BinaryenType {
     }
 }
 
+/// The wasm literal of a constant operand of type `ty`, or `None` for the constants which aren't
+/// supported yet.
+fn const_literal<'tcx>(value: &ConstVal, ty: Ty<'tcx>) -> Option<BinaryenLiteral> {
+    // TODO: handle more Rust types here
+    let lit = unsafe {
+        match *value {
+            // signed values narrower than 32 bits are sign-extended
+            ConstVal::Integral(ConstInt::I8(val)) => BinaryenLiteralInt32(val as i32),
+            ConstVal::Integral(ConstInt::I16(val)) => BinaryenLiteralInt32(val as i32),
+            ConstVal::Integral(ConstInt::I32(val)) => BinaryenLiteralInt32(val),
+            ConstVal::Integral(ConstInt::I64(val)) => BinaryenLiteralInt64(val),
+            // TODO: Since we're at the wasm32 stage, and until wasm64, it's probably best if isize
+            // and usize are always i32 ?
+            ConstVal::Integral(ConstInt::Isize(ConstIsize::Is16(val))) => {
+                BinaryenLiteralInt32(val as i32)
+            }
+            ConstVal::Integral(ConstInt::Isize(ConstIsize::Is32(val))) => BinaryenLiteralInt32(val),
+            ConstVal::Integral(ConstInt::Isize(ConstIsize::Is64(val))) => {
+                BinaryenLiteralInt32(val as i32)
+            }
+            // unsigned values narrower than 32 bits are zero-extended, and the wider ones keep
+            // their bit pattern
+            ConstVal::Integral(ConstInt::U8(val)) => BinaryenLiteralInt32(val as i32),
+            ConstVal::Integral(ConstInt::U16(val)) => BinaryenLiteralInt32(val as i32),
+            ConstVal::Integral(ConstInt::U32(val)) => BinaryenLiteralInt32(val as i32),
+            ConstVal::Integral(ConstInt::U64(val)) => BinaryenLiteralInt64(val as i64),
+            ConstVal::Integral(ConstInt::Usize(ConstUsize::Us16(val))) => {
+                BinaryenLiteralInt32(val as i32)
+            }
+            ConstVal::Integral(ConstInt::Usize(ConstUsize::Us32(val))) => {
+                BinaryenLiteralInt32(val as i32)
+            }
+            ConstVal::Integral(ConstInt::Usize(ConstUsize::Us64(val))) => {
+                BinaryenLiteralInt32(val as i32)
+            }
+            ConstVal::Float(ConstFloat::F32(val)) => BinaryenLiteralFloat32(val),
+            ConstVal::Float(ConstFloat::F64(val)) => BinaryenLiteralFloat64(val),
+            // a float literal whose inference didn't settle has the operand's type
+            ConstVal::Float(ConstFloat::FInfer { f32, f64 }) => {
+                match ty.sty {
+                    ty::TyFloat(FloatTy::F32) => BinaryenLiteralFloat32(f32),
+                    _ => BinaryenLiteralFloat64(f64),
+                }
+            }
+            ConstVal::Bool(val) => BinaryenLiteralInt32(if val { 1 } else { 0 }),
+            _ => return None,
+        }
+    };
+    Some(lit)
+}
+
+// The predicates below decide which MIR constructs `BinaryenFnCtxt` translates. They are shared
+// with `UnsupportedVisitor`, so that `--report-unsupported` lists what translation rejects.
+
+/// The wasm operation of a binary op, or `None` for the ops which aren't supported yet.
+fn binary_op(op: BinOp) -> Option<BinaryenOp> {
+    // TODO: match on the operand types to implement binary ops for other types than just i32s
+    // TODO: check if the operands are signed or not (CEnum, etc)
+    // TODO: comparisons are signed only for now, so implement unsigned ones
+    let op = unsafe {
+        match op {
+            BinOp::Add => BinaryenAddInt32(),
+            BinOp::Sub => BinaryenSubInt32(),
+            BinOp::Mul => BinaryenMulInt32(),
+            BinOp::Div => BinaryenDivSInt32(),
+            BinOp::BitAnd => BinaryenAndInt32(),
+            BinOp::BitOr => BinaryenOrInt32(),
+            BinOp::BitXor => BinaryenXorInt32(),
+            BinOp::Eq => BinaryenEqInt32(),
+            BinOp::Ne => BinaryenNeInt32(),
+            BinOp::Lt => BinaryenLtSInt32(),
+            BinOp::Le => BinaryenLeSInt32(),
+            BinOp::Gt => BinaryenGtSInt32(),
+            BinOp::Ge => BinaryenGeSInt32(),
+            _ => return None,
+        }
+    };
+    Some(op)
+}
+
+/// Whether a type with the `layout` is zero-sized, see `BinaryenFnCtxt::is_zst`.
+fn is_zst_layout(layout: &Layout, data_layout: &layout::TargetDataLayout) -> bool {
+    !layout.is_unsized() && layout.size(data_layout).bytes() == 0
+}
+
+/// Whether a checked binary op computes its overflow flag, on integers.
+fn checked_binary_op_supported(op: BinOp) -> bool {
+    match op {
+        BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Shl | BinOp::Shr => true,
+        _ => false,
+    }
+}
+
+/// The representation an unary op works on: `-` applies to floats, `!` to bools, and both to
+/// integers.
+enum UnaryRepr {
+    Float(u32),
+    Bool,
+    Int(u32, bool),
+}
+
+/// How `op` applies to a `ty` with the `layout`, or `None` when it isn't supported.
+fn unary_op_repr<'tcx>(op: UnOp, ty: Ty<'tcx>, layout: &Layout) -> Option<UnaryRepr> {
+    if let Some(float_bits) = rust_float_bits(ty) {
+        return match op {
+            UnOp::Neg => Some(UnaryRepr::Float(float_bits)),
+            UnOp::Not => None,
+        };
+    }
+    if ty.is_bool() {
+        return match op {
+            UnOp::Not => Some(UnaryRepr::Bool),
+            UnOp::Neg => None,
+        };
+    }
+    int_repr(ty, layout).map(|(bits, signed)| UnaryRepr::Int(bits, signed))
+}
+
+/// Whether aggregates of this kind are stored to their destination.
+fn aggregate_supported(kind: &AggregateKind) -> bool {
+    match *kind {
+        AggregateKind::Adt(..) |
+        AggregateKind::Tuple |
+        AggregateKind::Vec => true,
+        AggregateKind::Closure(..) => false,
+    }
+}
+
+/// Bit width and signedness of the integer representation of a `ty` with the `layout`, for casts.
+/// Thin pointers and fn pointers are `u32`s.
+fn int_repr<'tcx>(ty: Ty<'tcx>, layout: &Layout) -> Option<(u32, bool)> {
+    match ty.sty {
+        ty::TyBool => Some((8, false)),
+        ty::TyChar => Some((32, false)),
+        ty::TyInt(int_ty) => {
+            let bits = match int_ty {
+                IntTy::I8 => 8,
+                IntTy::I16 => 16,
+                IntTy::I64 => 64,
+                // isize is 32 bits on wasm32
+                _ => 32,
+            };
+            Some((bits, true))
+        }
+        ty::TyUint(uint_ty) => {
+            let bits = match uint_ty {
+                UintTy::U8 => 8,
+                UintTy::U16 => 16,
+                UintTy::U64 => 64,
+                _ => 32,
+            };
+            Some((bits, false))
+        }
+        ty::TyFnPtr(_) => Some((32, false)),
+        ty::TyRawPtr(_) | ty::TyRef(..) | ty::TyBox(_) if !is_fat_ptr(layout) => Some((32, false)),
+        _ => {
+            match *layout {
+                Layout::CEnum { discr, signed, .. } => {
+                    // the discriminant lives in an i32 local
+                    Some((::std::cmp::min(discr.size().bits() as u32, 32), signed))
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+fn is_fat_ptr(layout: &Layout) -> bool {
+    match *layout {
+        Layout::FatPointer { .. } => true,
+        _ => false,
+    }
+}
+
+/// The lowering of an `as` cast between scalars: integers, floats, C-like enums, bools, chars and
+/// pointers.
+enum MiscCast {
+    /// Between float widths, in bits
+    Float(u32, u32),
+    /// From a float width to an integer representation
+    FloatToInt(u32, (u32, bool)),
+    /// From an integer representation to a float width
+    IntToFloat((u32, bool), u32),
+    /// From a fat pointer, to a fat pointer when `true`, else to its data pointer
+    FatPtr(bool),
+    /// Between integer representations
+    Int((u32, bool), (u32, bool)),
+}
+
+/// How to cast a `src_ty` to a `dest_ty`, with their layouts, or `None` when it isn't supported.
+fn misc_cast<'tcx>(src_ty: Ty<'tcx>,
+                   src_layout: &Layout,
+                   dest_ty: Ty<'tcx>,
+                   dest_layout: &Layout)
+                   -> Option<MiscCast> {
+    match (rust_float_bits(src_ty), rust_float_bits(dest_ty)) {
+        (Some(src_bits), Some(dest_bits)) => Some(MiscCast::Float(src_bits, dest_bits)),
+        (Some(src_bits), None) => {
+            int_repr(dest_ty, dest_layout).map(|dest| MiscCast::FloatToInt(src_bits, dest))
+        }
+        (None, Some(dest_bits)) => {
+            int_repr(src_ty, src_layout).map(|src| MiscCast::IntToFloat(src, dest_bits))
+        }
+        (None, None) if is_fat_ptr(src_layout) => Some(MiscCast::FatPtr(is_fat_ptr(dest_layout))),
+        (None, None) => {
+            match (int_repr(src_ty, src_layout), int_repr(dest_ty, dest_layout)) {
+                (Some(src), Some(dest)) => Some(MiscCast::Int(src, dest)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// The second word of the fat pointer made by an unsizing coercion.
+enum UnsizeExtra<'tcx> {
+    /// The length of a slice, coerced from an array
+    Length(usize),
+    /// The vtable of a trait object
+    Vtable(ty::PolyTraitRef<'tcx>),
+}
+
+/// The extra word of an unsizing coercion from a `src_ty` to a `dest_ty` pointer, or `None` when
+/// it isn't supported.
+fn unsize_extra<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>,
+                          src_ty: Ty<'tcx>,
+                          dest_ty: Ty<'tcx>)
+                          -> Option<UnsizeExtra<'tcx>> {
+    let pointee = |ty: Ty<'tcx>| {
+        ty.builtin_deref(true, ty::LvaluePreference::NoPreference).map(|mt| mt.ty)
+    };
+    let (src_pointee, dest_pointee) = match (pointee(src_ty), pointee(dest_ty)) {
+        (Some(src_pointee), Some(dest_pointee)) => (src_pointee, dest_pointee),
+        _ => return None,
+    };
+    match (&src_pointee.sty, &dest_pointee.sty) {
+        (&ty::TyArray(_, len), &ty::TySlice(_)) => Some(UnsizeExtra::Length(len)),
+        // coercions between trait objects need the upcast vtable
+        (&ty::TyTrait(_), _) => None,
+        (_, &ty::TyTrait(ref object)) => {
+            Some(UnsizeExtra::Vtable(object.principal.with_self_ty(tcx, src_pointee)))
+        }
+        _ => None,
+    }
+}
+
+/// Whether dropping a `ty` has any effect.
+fn needs_drop<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: Ty<'tcx>) -> bool {
+    let param_env = tcx.empty_parameter_environment();
+    tcx.type_needs_drop_given_env(ty, &param_env)
+}
+
+/// The types of the fields dropped after the destructor of a `ty`, or `None` for the types whose
+/// drops aren't supported yet: enums and boxes.
+fn drop_field_tys<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: Ty<'tcx>) -> Option<Vec<Ty<'tcx>>> {
+    match ty.sty {
+        ty::TyAdt(adt_def, substs) if adt_def.adt_kind() == ty::AdtKind::Struct => {
+            Some(adt_def.struct_variant().fields.iter().map(|f| f.ty(tcx, substs)).collect())
+        }
+        ty::TyTuple(tys) => Some(tys.to_vec()),
+        _ => None,
+    }
+}
+
+/// The type, `ty` or one of the fields dropped with it, whose drop isn't supported.
+fn unsupported_drop_ty<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: Ty<'tcx>) -> Option<Ty<'tcx>> {
+    if !needs_drop(tcx, ty) {
+        return None;
+    }
+    match drop_field_tys(tcx, ty) {
+        Some(field_tys) => {
+            field_tys.into_iter().filter_map(|ty| unsupported_drop_ty(tcx, ty)).next()
+        }
+        None => Some(ty),
+    }
+}
+
+fn rust_float_bits<'tcx>(t: Ty<'tcx>) -> Option<u32> {
+    match t.sty {
+        ty::TyFloat(FloatTy::F32) => Some(32),
+        ty::TyFloat(FloatTy::F64) => Some(64),
+        _ => None,
+    }
+}
+
+/// The width in bytes, signedness and wasm type used to access a `t` in linear memory.
+fn rust_ty_mem_access<'tcx>(t: Ty<'tcx>) -> (u32, bool, BinaryenType) {
+    match t.sty {
+        ty::TyBool |
+        ty::TyUint(UintTy::U8) => (1, false, BinaryenInt32()),
+        ty::TyInt(IntTy::I8) => (1, true, BinaryenInt32()),
+        ty::TyUint(UintTy::U16) => (2, false, BinaryenInt32()),
+        ty::TyInt(IntTy::I16) => (2, true, BinaryenInt32()),
+        ty::TyInt(IntTy::I64) |
+        ty::TyUint(UintTy::U64) => (8, false, BinaryenInt64()),
+        ty::TyFloat(FloatTy::F32) => (4, false, BinaryenFloat32()),
+        ty::TyFloat(FloatTy::F64) => (8, false, BinaryenFloat64()),
+        _ => (4, false, BinaryenInt32()),
+    }
+}
+
 fn rust_ty_to_builder<'tcx>(t: Ty<'tcx>) -> builder::Type {
     use binaryen::builder::ReprType::*;
 
@@ -1751,8 +4711,554 @@ fn sanitize_symbol(s: &str) -> String {
 
 #[derive(Debug)]
 enum BinaryenCallKind {
-    Direct,
-    Import, // Indirect // unimplemented at the moment
+    Direct(*const c_char),
+    Import(*const c_char),
+    Indirect(BinaryenExpressionRef, BinaryenFunctionTypeRef),
+}
+
+/// Support functions emitted into the module, or imported from the host, the first time
+/// translated code needs them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum RuntimeFn {
+    Memmove,
+    Memset,
+    Panic,
+    StackOverflow,
+    Unsupported,
+    /// Emitted once the memory layout is known
+    HeapBase,
+}
+
+/// The fns starting a panic, whose bodies report it to the host.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum PanicEntry {
+    /// `#[lang = "panic"]`, taking the message, file and line as a `&(&str, &str, u32)`
+    Panic,
+    /// `#[lang = "panic_fmt"]`, taking the `fmt::Arguments`, file and line
+    PanicFmt,
+    /// `std::panicking::begin_panic`, taking the message and a `&(&str, u32)`
+    BeginPanic,
+}
+
+/// The categories of MIR constructs and types which can't be translated yet, each reported with a
+/// stable error code.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum UnsupportedKind {
+    Statement,
+    Terminator,
+    Rvalue,
+    Operand,
+    Lvalue,
+    Cast,
+    Layout,
+    Intrinsic,
+    TraitSelection,
+}
+
+impl UnsupportedKind {
+    fn name(&self) -> &'static str {
+        match *self {
+            UnsupportedKind::Statement => "statement",
+            UnsupportedKind::Terminator => "terminator",
+            UnsupportedKind::Rvalue => "rvalue",
+            UnsupportedKind::Operand => "operand",
+            UnsupportedKind::Lvalue => "lvalue",
+            UnsupportedKind::Cast => "cast",
+            UnsupportedKind::Layout => "layout",
+            UnsupportedKind::Intrinsic => "intrinsic",
+            UnsupportedKind::TraitSelection => "trait selection",
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match *self {
+            UnsupportedKind::Statement => "E9001",
+            UnsupportedKind::Terminator => "E9002",
+            UnsupportedKind::Rvalue => "E9003",
+            UnsupportedKind::Operand => "E9004",
+            UnsupportedKind::Lvalue => "E9005",
+            UnsupportedKind::Cast => "E9006",
+            UnsupportedKind::Layout => "E9007",
+            UnsupportedKind::Intrinsic => "E9008",
+            UnsupportedKind::TraitSelection => "E9009",
+        }
+    }
+}
+
+/// A construct found while translating or classifying a fn, reported once the whole fn is
+/// translated.
+#[derive(Clone, Debug)]
+struct Unsupported {
+    kind: UnsupportedKind,
+    span: Span,
+    message: String,
+}
+
+/// An exported fn, which can be invoked by the interpreter.
+struct ExportedFn {
+    fn_name: CString,
+    params: Vec<BinaryenType>,
+    ret: BinaryenType,
+}
+
+/// What becomes of the fns using unsupported constructs.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum UnsupportedMode {
+    /// Their constructs are errors, and they are not emitted
+    Error,
+    /// Their constructs are warnings, and they are replaced by stubs
+    Stub,
+}
+
+/// A fn using unsupported constructs, replaced by a stub or listed in the report.
+struct UnsupportedFn {
+    path: String,
+    constructs: Vec<Unsupported>,
+}
+
+/// Classifies the unsupported constructs of every MIR body of the crate, including the generic
+/// fns which are never instantiated, without translating them.
+fn find_unsupported_fns<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
+                                  mir_map: &MirMap<'tcx>)
+                                  -> Vec<UnsupportedFn> {
+    let mut dids: Vec<DefId> = mir_map.map.keys().cloned().collect();
+    dids.sort();
+
+    let mut unsupported_fns = Vec::new();
+    for did in dids {
+        let mir = &mir_map.map[&did];
+        let mut visitor = UnsupportedVisitor {
+            tcx: tcx,
+            mir: mir,
+            span: mir.span,
+            constructs: Vec::new(),
+        };
+        visitor.visit_mir(mir);
+        if !visitor.constructs.is_empty() {
+            unsupported_fns.push(UnsupportedFn {
+                path: tcx.item_path_str(did),
+                constructs: visitor.constructs,
+            });
+        }
+    }
+    unsupported_fns
+}
+
+/// The unsupported constructs of the fns, grouped by kind.
+fn unsupported_report_json<'a, 'tcx>(tcx: &TyCtxt<'a, 'tcx, 'tcx>,
+                                     unsupported_fns: &[UnsupportedFn])
+                                     -> String {
+    let mut kinds: Vec<UnsupportedKind> = Vec::new();
+    for unsupported in unsupported_fns.iter().flat_map(|f| f.constructs.iter()) {
+        if !kinds.contains(&unsupported.kind) {
+            kinds.push(unsupported.kind);
+        }
+    }
+
+    let codemap = tcx.sess.codemap();
+    let mut total = 0;
+    let mut groups = Vec::new();
+    for kind in kinds {
+        let mut locations = Vec::new();
+        for unsupported_fn in unsupported_fns {
+            for unsupported in unsupported_fn.constructs.iter().filter(|u| u.kind == kind) {
+                let loc = codemap.lookup_char_pos(unsupported.span.lo);
+                locations.push(format!("{{\"fn\": {}, \"file\": {}, \"line\": {}, \
+                                        \"column\": {}, \"message\": {}}}",
+                                       json_string(&unsupported_fn.path),
+                                       json_string(&loc.file.name),
+                                       loc.line,
+                                       loc.col.0 + 1,
+                                       json_string(&unsupported.message)));
+            }
+        }
+        total += locations.len();
+        groups.push(format!("{{\"kind\": {}, \"code\": {}, \"count\": {}, \
+                             \"locations\": [{}]}}",
+                            json_string(kind.name()),
+                            json_string(kind.code()),
+                            locations.len(),
+                            locations.join(", ")));
+    }
+
+    format!("{{\"fns\": {}, \"total\": {}, \"constructs\": [{}]}}",
+            unsupported_fns.len(),
+            total,
+            groups.join(", "))
+}
+
+// The intrinsics lowered by `trans_intrinsic_call`, the others are reported as unsupported
+const INTRINSICS: &'static [&'static str] = &["size_of",
+                                              "min_align_of",
+                                              "transmute",
+                                              "copy",
+                                              "copy_nonoverlapping",
+                                              "write_bytes",
+                                              "offset",
+                                              "abort",
+                                              "unreachable",
+                                              "ctpop",
+                                              "ctlz",
+                                              "cttz",
+                                              "bswap",
+                                              "sqrtf32",
+                                              "sqrtf64",
+                                              "floorf32",
+                                              "floorf64",
+                                              "add_with_overflow",
+                                              "sub_with_overflow",
+                                              "mul_with_overflow",
+                                              "volatile_load",
+                                              "volatile_store"];
+
+/// Classifies the MIR constructs of a body like `BinaryenFnCtxt` handles them, without
+/// translating it: it can't fail, and it also covers the generic fns. The layouts of the types
+/// depending on type parameters are only known once instantiated, and are not checked.
+struct UnsupportedVisitor<'v, 'tcx: 'v> {
+    tcx: &'v TyCtxt<'v, 'tcx, 'tcx>,
+    /// The MIR body, or promoted constant, being classified
+    mir: &'v Mir<'tcx>,
+    /// The span of the statement or terminator being classified
+    span: Span,
+    constructs: Vec<Unsupported>,
+}
+
+impl<'v, 'tcx: 'v> UnsupportedVisitor<'v, 'tcx> {
+    fn unsupported(&mut self, kind: UnsupportedKind, message: String) {
+        debug!("unsupported {:?}: {}", kind, message);
+        self.constructs.push(Unsupported {
+            kind: kind,
+            span: self.span,
+            message: message,
+        });
+    }
+
+    /// The layout of a `ty`, unless it depends on the substs of a generic fn, or can't be computed.
+    fn layout(&self, ty: Ty<'tcx>) -> Option<&'tcx Layout> {
+        if ty.needs_subst() {
+            return None;
+        }
+        self.tcx.infer_ctxt(None, None, Reveal::All).enter(|infcx| ty.layout(&infcx).ok())
+    }
+
+    fn check_layout(&mut self, ty: Ty<'tcx>) {
+        if ty.needs_subst() {
+            return;
+        }
+        let error = self.tcx.infer_ctxt(None, None, Reveal::All).enter(|infcx| {
+            ty.layout(&infcx).err().map(|error| error.to_string())
+        });
+        if let Some(error) = error {
+            self.unsupported(UnsupportedKind::Layout, format!("layout of `{}`: {}", ty, error));
+        }
+    }
+
+    fn check_callee(&mut self, func: &Operand<'tcx>) {
+        if let Operand::Constant(Constant { literal: Literal::Item { def_id, .. }, .. }) = *func {
+            match self.tcx.lookup_item_type(def_id).ty.sty {
+                ty::TyFnDef(_, _, ref fn_ty) if fn_ty.abi == Abi::RustIntrinsic => {}
+                // the MIR of the fns of other crates isn't available
+                ty::TyFnDef(..) if !def_id.is_local() => {
+                    self.unsupported(UnsupportedKind::Terminator, format!("Call to {:?}", func));
+                    return;
+                }
+                _ => return,
+            }
+            let name = self.tcx.item_name(def_id).as_str();
+            if !INTRINSICS.contains(&&*name) {
+                self.unsupported(UnsupportedKind::Intrinsic, format!("intrinsic {}", name));
+            }
+        }
+    }
+}
+
+impl<'v, 'tcx: 'v> MirVisitor<'tcx> for UnsupportedVisitor<'v, 'tcx> {
+    fn visit_mir(&mut self, mir: &Mir<'tcx>) {
+        self.span = mir.span;
+        let mut local_tys = vec![mir.return_ty];
+        local_tys.extend(mir.arg_decls.iter().map(|decl| decl.ty));
+        local_tys.extend(mir.var_decls.iter().map(|decl| decl.ty));
+        local_tys.extend(mir.temp_decls.iter().map(|decl| decl.ty));
+        let mut checked_tys = Vec::new();
+        for ty in local_tys {
+            if !checked_tys.contains(&ty) {
+                self.check_layout(ty);
+                checked_tys.push(ty);
+            }
+        }
+
+        self.super_mir(mir);
+
+        let body = self.mir;
+        for promoted in body.promoted.iter() {
+            self.mir = promoted;
+            self.visit_mir(promoted);
+        }
+        self.mir = body;
+    }
+
+    fn visit_statement(&mut self,
+                       block: BasicBlock,
+                       statement: &Statement<'tcx>,
+                       location: Location) {
+        self.span = statement.source_info.span;
+        match statement.kind {
+            StatementKind::Assign(..) |
+            StatementKind::StorageLive(_) |
+            StatementKind::StorageDead(_) => {}
+            _ => {
+                self.unsupported(UnsupportedKind::Statement,
+                                 format!("statement {:?}", statement.kind));
+            }
+        }
+        self.super_statement(block, statement, location);
+    }
+
+    fn visit_terminator(&mut self,
+                        block: BasicBlock,
+                        terminator: &Terminator<'tcx>,
+                        location: Location) {
+        self.span = terminator.source_info.span;
+        self.super_terminator(block, terminator, location);
+    }
+
+    fn visit_terminator_kind(&mut self,
+                             block: BasicBlock,
+                             kind: &TerminatorKind<'tcx>,
+                             location: Location) {
+        match *kind {
+            TerminatorKind::Goto { .. } |
+            TerminatorKind::If { .. } |
+            TerminatorKind::Return |
+            TerminatorKind::Resume |
+            TerminatorKind::Assert { .. } => {}
+            TerminatorKind::Switch { ref discr, .. } => {
+                let tcx = *self.tcx;
+                let ty = discr.ty(self.mir, tcx).to_ty(tcx);
+                if let Some(layout) = self.layout(ty) {
+                    if is_zst_layout(layout, &tcx.data_layout) {
+                        self.unsupported(UnsupportedKind::Terminator,
+                                         format!("Switch on zero-sized {:?}", discr));
+                    }
+                }
+            }
+            TerminatorKind::Drop { ref location, .. } => {
+                let tcx = *self.tcx;
+                let ty = location.ty(self.mir, tcx).to_ty(tcx);
+                // the drops of generic values depend on the substs of the fn instance
+                if !ty.needs_subst() {
+                    if let Some(drop_ty) = unsupported_drop_ty(tcx, ty) {
+                        self.unsupported(UnsupportedKind::Terminator,
+                                         format!("Drop of {:?}, with type {:?}",
+                                                 location,
+                                                 drop_ty));
+                    }
+                }
+            }
+            TerminatorKind::Call { ref func, .. } => self.check_callee(func),
+            _ => self.unsupported(UnsupportedKind::Terminator, format!("terminator {:?}", kind)),
+        }
+        self.super_terminator_kind(block, kind, location);
+    }
+
+    fn visit_rvalue(&mut self, rvalue: &Rvalue<'tcx>, location: Location) {
+        let tcx = *self.tcx;
+        match *rvalue {
+            Rvalue::Use(_) |
+            Rvalue::Ref(..) => {}
+            Rvalue::UnaryOp(op, ref operand) => {
+                let ty = operand.ty(self.mir, tcx);
+                if let Some(layout) = self.layout(ty) {
+                    if unary_op_repr(op, ty, layout).is_none() {
+                        self.unsupported(UnsupportedKind::Rvalue,
+                                         format!("UnOp {:?} on {:?}", op, ty));
+                    }
+                }
+            }
+            Rvalue::BinaryOp(op, ..) => {
+                if binary_op(op).is_none() {
+                    self.unsupported(UnsupportedKind::Rvalue, format!("BinOp {:?}", op));
+                }
+            }
+            Rvalue::CheckedBinaryOp(op, ref left, ref right) => {
+                let ty = left.ty(self.mir, tcx);
+                let shift_ty = right.ty(self.mir, tcx);
+                if let (Some(layout), Some(shift_layout)) = (self.layout(ty),
+                                                             self.layout(shift_ty)) {
+                    if int_repr(ty, layout).is_none() || !checked_binary_op_supported(op) {
+                        self.unsupported(UnsupportedKind::Rvalue,
+                                         format!("checked BinaryOp {:?} on {:?}", op, ty));
+                    } else if (op == BinOp::Shl || op == BinOp::Shr) &&
+                              int_repr(shift_ty, shift_layout).is_none() {
+                        self.unsupported(UnsupportedKind::Rvalue,
+                                         format!("shift amount of type {:?}", shift_ty));
+                    }
+                }
+            }
+            Rvalue::Aggregate(ref kind, _) => {
+                if !aggregate_supported(kind) {
+                    self.unsupported(UnsupportedKind::Rvalue,
+                                     format!("Assign Aggregate {:?}", kind));
+                }
+            }
+            Rvalue::Cast(CastKind::Misc, ref operand, dest_ty) => {
+                let src_ty = operand.ty(self.mir, tcx);
+                if let (Some(src_layout), Some(dest_layout)) = (self.layout(src_ty),
+                                                                self.layout(dest_ty)) {
+                    if misc_cast(src_ty, src_layout, dest_ty, dest_layout).is_none() {
+                        self.unsupported(UnsupportedKind::Cast,
+                                         format!("Cast from {:?} to {:?}", src_ty, dest_ty));
+                    }
+                }
+            }
+            Rvalue::Cast(CastKind::ReifyFnPointer, ref operand, _) => {
+                match *operand {
+                    Operand::Constant(Constant { literal: Literal::Item { .. }, .. }) => {}
+                    _ => {
+                        self.unsupported(UnsupportedKind::Cast,
+                                         format!("ReifyFnPointer of {:?}", operand));
+                    }
+                }
+            }
+            Rvalue::Cast(CastKind::UnsafeFnPointer, ..) => {}
+            Rvalue::Cast(CastKind::Unsize, ref operand, dest_ty) => {
+                let src_ty = operand.ty(self.mir, tcx);
+                if unsize_extra(tcx, src_ty, dest_ty).is_none() {
+                    self.unsupported(UnsupportedKind::Cast,
+                                     format!("Unsize Cast from {:?} to {:?}", src_ty, dest_ty));
+                }
+            }
+            _ => self.unsupported(UnsupportedKind::Rvalue, format!("rvalue {:?}", rvalue)),
+        }
+        self.super_rvalue(rvalue, location);
+    }
+
+    fn visit_operand(&mut self, operand: &Operand<'tcx>, location: Location) {
+        if let Operand::Constant(ref constant) = *operand {
+            match constant.literal {
+                Literal::Item { .. } => {}
+                Literal::Value { ref value } => {
+                    if const_literal(value, constant.ty).is_none() {
+                        self.unsupported(UnsupportedKind::Operand,
+                                         format!("constant value: {:?}", value));
+                    }
+                }
+                Literal::Promoted { .. } => {
+                    self.unsupported(UnsupportedKind::Operand,
+                                     format!("Promoted Literal: {:?}", constant));
+                }
+            }
+        }
+        self.super_operand(operand, location);
+    }
+
+    fn visit_lvalue(&mut self,
+                    lvalue: &Lvalue<'tcx>,
+                    context: LvalueContext,
+                    location: Location) {
+        match *lvalue {
+            Lvalue::Static(_) => {
+                self.unsupported(UnsupportedKind::Lvalue, format!("Lvalue: {:?}", lvalue));
+            }
+            Lvalue::Projection(ref projection) => {
+                let tcx = *self.tcx;
+                let base_ty = projection.base.ty(self.mir, tcx).to_ty(tcx);
+                let base_layout = self.layout(base_ty);
+                match projection.elem {
+                    ProjectionElem::Deref => {
+                        if projection_offset(&projection.base).is_some() {
+                            self.unsupported(UnsupportedKind::Lvalue,
+                                             format!("Deref {:?}", lvalue));
+                        }
+                    }
+                    ProjectionElem::Field(..) => {
+                        match base_layout {
+                            None |
+                            Some(&Layout::Univariant { .. }) => {}
+                            Some(&Layout::General { .. }) => {
+                                if projection_offset(&projection.base) != Some(true) {
+                                    self.unsupported(UnsupportedKind::Lvalue,
+                                                     format!("Field Projection of enum without \
+                                                              variant: {:?}",
+                                                             projection));
+                                }
+                            }
+                            Some(_) => {
+                                self.unsupported(UnsupportedKind::Lvalue,
+                                                 format!("Field Projection: {:?}", projection));
+                            }
+                        }
+                    }
+                    ProjectionElem::Downcast(..) => {
+                        match base_layout {
+                            None => {}
+                            Some(&Layout::General { .. }) => {
+                                if projection_offset(&projection.base).is_some() {
+                                    self.unsupported(UnsupportedKind::Lvalue,
+                                                     format!("Downcast Projection with offset: \
+                                                              {:?}",
+                                                             projection));
+                                }
+                            }
+                            Some(_) => {
+                                self.unsupported(UnsupportedKind::Lvalue,
+                                                 format!("Downcast Projection: {:?}",
+                                                         projection));
+                            }
+                        }
+                    }
+                    _ => {
+                        self.unsupported(UnsupportedKind::Lvalue,
+                                         format!("Projection: {:?}", projection));
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.super_lvalue(lvalue, context, location);
+    }
+}
+
+/// Whether `trans_lval` translates an `lvalue` to an offset from its local, as it does for the
+/// fields and variants in linear memory: `Some(true)` for variants, `Some(false)` for fields.
+fn projection_offset(lvalue: &Lvalue) -> Option<bool> {
+    match *lvalue {
+        Lvalue::Projection(ref projection) => {
+            match projection.elem {
+                ProjectionElem::Downcast(..) => Some(true),
+                ProjectionElem::Field(..) => Some(false),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Quotes and escapes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// The fixed layout of a fn's frame on the shadow stack, allocated by the prologue and freed by
+/// the epilogue.
+struct Frame {
+    /// The wasm local holding the address of the frame, the stack pointer during the fn
+    base_local: BinaryenIndex,
+    size: u32,
+    /// The offset in the frame of the values of the wasm locals living in linear memory, by local
+    slots: HashMap<u32, u32>,
 }
 
 enum BinaryenBlockKind {
@@ -0,0 +1,50 @@
+#![feature(lang_items, no_core)]
+#![crate_type = "lib"]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+// every unsupported construct of the crate is reported, not just the first one
+
+pub fn half() -> f64 {
+    0.5 //~ ERROR unsupported constant value
+}
+
+pub fn quarter() -> f32 {
+    0.25 //~ ERROR unsupported constant value
+}
+
+// the errors carry the error code of the kind of construct
+
+#[lang = "sync"]
+unsafe trait Sync {}
+
+impl Copy for i32 {}
+unsafe impl Sync for i32 {}
+
+static ANSWER: i32 = 42;
+
+pub fn answer() -> i32 {
+    ANSWER //~ ERROR E9005
+}
+
+pub enum Void {}
+
+pub fn absurd(void: Void) -> i32 {
+    match void {} //~ ERROR E9002
+}
+
+pub trait Answer {
+    fn answer(&self) -> i32;
+}
+
+pub fn virtual_answer(answer: &Answer) -> i32 {
+    answer.answer() //~ ERROR E9009
+}
+
+// Only assignments and storage markers reach the translation before the MIR optimizations run, so
+// there is no statement to exercise the E9001 code.
@@ -0,0 +1,23 @@
+#![feature(lang_items, no_core)]
+#![crate_type = "cdylib"]
+#![no_core]
+
+#[lang="sized"]
+trait Sized {}
+
+#[lang="copy"]
+trait Copy {}
+
+fn double(a: i32) -> i32 {
+    a * 2
+}
+
+#[no_mangle]
+pub extern fn exported(a: i32) -> i32 {
+    double(a)
+}
+
+#[export_name = "renamed"]
+pub fn internal_name(a: i32) -> i32 {
+    a
+}
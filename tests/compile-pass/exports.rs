@@ -0,0 +1,33 @@
+// ignore-interpreter-output
+// ignore-vm-output
+#![feature(lang_items, no_core)]
+#![crate_type = "cdylib"]
+#![no_core]
+
+#[lang="sized"]
+trait Sized {}
+
+#[lang="copy"]
+trait Copy {}
+
+#[no_mangle]
+pub extern fn exported() -> i32 {
+    internal::hidden()
+}
+
+pub mod api {
+    pub extern fn nested() -> i32 {
+        2
+    }
+}
+
+// `pub`, but not reachable from other crates
+mod internal {
+    pub extern fn hidden() -> i32 {
+        3
+    }
+}
+
+//~ (export "exported"
+//~ (export "nested"
+//~! (export "hidden"
@@ -0,0 +1,33 @@
+#![feature(lang_items, no_core, custom_attribute)]
+#![allow(dead_code)]
+#![no_core]
+
+#[lang="sized"]
+trait Sized {}
+
+#[lang="copy"]
+trait Copy {}
+
+// imported from "env"
+extern {
+    fn env_fn(a: i32, b: i64) -> i64;
+}
+
+#[link(name = "host")]
+extern {
+    #[link_name = "log"]
+    fn host_log(value: f64);
+}
+
+#[wasm_import_module = "other"]
+extern {
+    fn other_fn() -> f32;
+}
+
+fn main() {
+    unsafe {
+        let a = env_fn(1, 2);
+        host_log(a as f64);
+        let b = other_fn();
+    }
+}
@@ -0,0 +1,18 @@
+// compile-flags: --invoke add 40 2
+#![feature(lang_items, no_core)]
+#![crate_type = "cdylib"]
+#![no_core]
+
+#[lang="sized"]
+trait Sized {}
+
+#[lang="copy"]
+trait Copy {}
+
+// the interpreter prints the result, V8 doesn't run the test as it only runs the start fn
+#[no_mangle]
+pub extern fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+//~ (i32.const 42)
@@ -0,0 +1,39 @@
+// compile-flags: --report-unsupported=json
+#![feature(lang_items, no_core)]
+#![crate_type = "lib"]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "sync"]
+unsafe trait Sync {}
+
+#[lang = "rem"]
+trait Rem<RHS = Self> {
+    type Output;
+    fn rem(self, rhs: RHS) -> Self::Output;
+}
+
+impl Copy for i32 {}
+unsafe impl Sync for i32 {}
+
+static ANSWER: i32 = 42;
+
+// generic fns are reported even if they are never instantiated
+pub fn answer<T>(_question: T) -> i32 {
+    ANSWER
+}
+
+// the report rejects the binary ops translation rejects
+impl Rem for i32 {
+    type Output = i32;
+    fn rem(self, rhs: i32) -> i32 {
+        self % rhs
+    }
+}
+
+//~ {"fns": 2, "total": 2, "constructs": [{"kind": "lvalue", "code": "E9005", "count": 1, "locations": [{"fn": "answer", "file": "tests/compile-pass/report-unsupported.rs", "line": 28, "column": 5, "message": "Lvalue: ANSWER"}]}, {"kind": "rvalue", "code": "E9003", "count": 1, "locations": [{"fn": "<i32 as Rem>::rem", "file": "tests/compile-pass/report-unsupported.rs", "line": 35, "column": 9, "message": "BinOp Rem"}]}]}
@@ -37,7 +37,8 @@ fn get_target_dir<'a>() -> &'a Path {
 /// ```
 ///
 /// This function will extract all of the test strings from the `//~`
-/// comments.
+/// comments. Text which must not occur anywhere in the output is
+/// prefixed by `//~!`, and kept with its leading `!`.
 fn get_expected_outputs(filename: &Path) -> Vec<String> {
     let mut outputs = Vec::new();
     let file = File::open(filename).expect("could not open file");
@@ -61,6 +62,56 @@ fn get_expected_outputs(filename: &Path) -> Vec<String> {
     outputs
 }
 
+/// Gets the extra mir2wasm flags of a test, given in a `// compile-flags:` comment, like in the
+/// compile-fail tests.
+fn get_compile_flags(filename: &Path) -> Vec<String> {
+    let file = File::open(filename).expect("could not open file");
+    let file = BufReader::new(file);
+
+    let directive = "// compile-flags:";
+    for line in file.lines() {
+        let line = line.unwrap();
+        if line.starts_with(directive) {
+            return line[directive.len()..].split_whitespace().map(|s| s.to_owned()).collect();
+        }
+    }
+
+    Vec::new()
+}
+
+/// Gets the paths of the artifacts a test writes next to its binary module, from the kinds of its
+/// `--emit=KINDS` flag.
+fn get_emitted_artifacts(filename: &Path, outwasm: &Path) -> Vec<PathBuf> {
+    let flag = "--emit=";
+    get_compile_flags(filename)
+        .iter()
+        .filter(|arg| arg.starts_with(flag))
+        .flat_map(|arg| arg[flag.len()..].split(',').map(move |kind| outwasm.with_extension(kind)))
+        .collect()
+}
+
+/// Checks whether the artifacts were written.
+fn check_artifacts(artifacts: &[PathBuf]) -> bool {
+    for artifact in artifacts {
+        if !artifact.exists() {
+            let stderr = std::io::stderr();
+            writeln!(stderr.lock(), "[mir2wasm] FAILED: {} was not written", artifact.display())
+                .unwrap();
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether a test has a `// NAME` directive comment, like `// ignore-interpreter-output`.
+fn has_directive(filename: &Path, name: &str) -> bool {
+    let file = File::open(filename).expect("could not open file");
+    let file = BufReader::new(file);
+
+    let directive = format!("// {}", name);
+    file.lines().any(|line| line.unwrap().trim() == directive)
+}
+
 /// Checks whether the stdout bytes includes all of the expected
 /// strings in the right order.
 ///
@@ -68,8 +119,20 @@ fn get_expected_outputs(filename: &Path) -> Vec<String> {
 /// consecutive, just in the right order. However, only one test
 /// string is allowed per line.
 fn match_stdout(stdout: &Vec<u8>, expected: &[String]) -> Result<(), ()> {
-    let mut stdout = str::from_utf8(stdout).unwrap().lines();
+    let (unexpected, expected): (Vec<_>, Vec<_>) =
+        expected.iter().partition(|expect| expect.starts_with("!"));
+    let stdout = str::from_utf8(stdout).unwrap();
 
+    for unexpect in unexpected {
+        let unexpect = unexpect[1..].trim();
+        if stdout.lines().any(|line| line.contains(unexpect)) {
+            let stderr = std::io::stderr();
+            writeln!(stderr.lock(), "unexpected string {} found", unexpect).unwrap();
+            return Err(());
+        }
+    }
+
+    let mut stdout = stdout.lines();
     for expect in expected {
         loop {
             match stdout.next() {
@@ -162,9 +225,41 @@ fn run_and_check_output(vm: &str, mut cmd: std::process::Command, expected: &[St
     }
 }
 
+/// Runs a command which should fail, like a test trapping at runtime, and checks whether the
+/// expected output was produced before the failure.
+fn run_and_check_failure(vm: &str, mut cmd: std::process::Command, expected: &[String]) -> bool {
+    let stderr = std::io::stderr();
+    match cmd.output() {
+        Ok(ref output) if !output.status.success() => {
+            match match_stdout(&output.stdout, expected) {
+                Ok(()) => {
+                    writeln!(stderr.lock(), "[{}] ok, failed as expected", vm).unwrap();
+                    return true;
+                }
+                Err(()) => {
+                    writeln!(stderr.lock(), "[{}] Test execution failed", vm).unwrap();
+                    return false;
+                }
+            }
+        }
+        Ok(_) => {
+            let mut stderr = stderr.lock();
+            writeln!(stderr, "[{}] FAILED: expected a failure, but exited successfully", vm)
+                .unwrap();
+            writeln!(stderr, "cmd: \n {:?}", cmd).unwrap();
+            return false;
+        }
+        Err(e) => {
+            writeln!(stderr.lock(), "[{}] FAILED: {}", vm, e).unwrap();
+            return false;
+        }
+    }
+}
+
 struct TestSuite<'a> {
     name: &'a str,
     run: bool,
+    should_fail: bool,
     path: String,
 }
 
@@ -173,6 +268,7 @@ impl<'a> TestSuite<'a> {
         TestSuite {
             name: name,
             run: false,
+            should_fail: false,
             path: format!("tests/{}", name),
         }
     }
@@ -182,6 +278,12 @@ impl<'a> TestSuite<'a> {
         self
     }
 
+    /// The tests compile, but running them fails, after printing the expected output.
+    fn set_should_fail(&mut self, should_fail: bool) -> &'a mut TestSuite {
+        self.should_fail = should_fail;
+        self
+    }
+
     fn path(&mut self, path: &str) -> &'a mut TestSuite {
         self.path = String::from_str(path).unwrap();
         self
@@ -232,6 +334,12 @@ impl<'a> TestSuite<'a> {
                 if outwasm.exists() {
                     fs::remove_file(&outwasm).expect("could not delete previous test");
                 }
+                let artifacts = get_emitted_artifacts(&path, &outwasm);
+                for artifact in &artifacts {
+                    if artifact.exists() {
+                        fs::remove_file(artifact).expect("could not delete previous artifact");
+                    }
+                }
 
                 let stderr = std::io::stderr();
                 write!(stderr.lock(),
@@ -239,27 +347,60 @@ impl<'a> TestSuite<'a> {
                        self.name,
                        path.display())
                     .unwrap();
-                let mut cmd = std::process::Command::new(mir2wasm);
-                cmd.arg(&path);
-                cmd.arg("-Dwarnings");
-                if self.run {
-                    cmd.arg("--run");
-                }
-                cmd.arg("-o");
-                cmd.arg(&outwasm);
-                let libs = Path::new(&sysroot).join("lib");
-                let sysroot = libs.join("rustlib").join(&target).join("lib");
-                let paths = std::env::join_paths(&[libs, sysroot]).unwrap();
-                cmd.env(compiletest::procsrv::dylib_env_var(), paths);
+                let mir2wasm_cmd = |run: bool| {
+                    let mut cmd = std::process::Command::new(mir2wasm);
+                    cmd.arg(&path);
+                    cmd.arg("-Dwarnings");
+                    cmd.args(&get_compile_flags(&path));
+                    if run {
+                        cmd.arg("--run");
+                    }
+                    cmd.arg("-o");
+                    cmd.arg(&outwasm);
+                    let libs = Path::new(&sysroot).join("lib");
+                    let sysroot = libs.join("rustlib").join(&target).join("lib");
+                    let paths = std::env::join_paths(&[libs, sysroot]).unwrap();
+                    cmd.env(compiletest::procsrv::dylib_env_var(), paths);
+                    cmd
+                };
 
                 let expected = get_expected_outputs(&path);
-
-                if run_and_check_output("binaryen", cmd, expected.as_slice()) {
-                    if run_in_vm(&outwasm, expected.as_slice()) {
-                        pass += 1;
+                // The Binaryen interpreter only provides the spectest imports, and traps at calls
+                // to the others, like panic reports: tests printing after those only check their
+                // output in the VM. Conversely, the output of the compiler, like the printed
+                // module, is not in the interpreter's or the VM's.
+                let ignored = |directive| if has_directive(&path, directive) {
+                    &[][..]
+                } else {
+                    expected.as_slice()
+                };
+                let interpreter_expected = ignored("ignore-interpreter-output");
+                let vm_expected = ignored("ignore-vm-output");
+                // The unsupported constructs report doesn't write a module to run, and V8 only runs
+                // the start fn, not the exported fn given to `--invoke`. The invocation is
+                // interpreted once the module is written, even without `--run`.
+                let flags = get_compile_flags(&path);
+                let invoke = flags.iter().any(|flag| flag == "--invoke");
+                let report = flags.iter().any(|flag| flag.starts_with("--report-unsupported"));
+                let vm = !invoke && !report;
+
+                let passed = if self.should_fail {
+                    // the interpreter fails before writing the module, so compile it first
+                    (invoke || run_and_check_output("mir2wasm", mir2wasm_cmd(false), &[])) &&
+                    run_and_check_failure("binaryen", mir2wasm_cmd(true), interpreter_expected) &&
+                    (!vm || run_in_vm(&outwasm, vm_expected, true))
+                } else {
+                    let binaryen_expected = if self.run {
+                        interpreter_expected
                     } else {
-                        fail += 1;
-                    }
+                        expected.as_slice()
+                    };
+                    run_and_check_output("binaryen", mir2wasm_cmd(self.run), binaryen_expected) &&
+                    check_artifacts(&artifacts) &&
+                    (!vm || run_in_vm(&outwasm, vm_expected, false))
+                };
+                if passed {
+                    pass += 1;
                 } else {
                     fail += 1;
                 }
@@ -280,7 +421,7 @@ impl<'a> TestSuite<'a> {
 }
 
 #[cfg(target_os="linux")]
-fn run_in_vm(wasm: &Path, expected: &[String]) -> bool {
+fn run_in_vm(wasm: &Path, expected: &[String], should_fail: bool) -> bool {
     let d8 = Path::new("./wasm-install/bin/d8");
     let rt = Path::new("./rt/rustrt.js");
 
@@ -290,11 +431,15 @@ fn run_in_vm(wasm: &Path, expected: &[String]) -> bool {
         .arg("--")
         .arg(wasm);
 
-    run_and_check_output("V8", cmd, expected)
+    if should_fail {
+        run_and_check_failure("V8", cmd, expected)
+    } else {
+        run_and_check_output("V8", cmd, expected)
+    }
 }
 
 #[cfg(not(target_os="linux"))]
-fn run_in_vm(_wasm: &Path, _expected: &[String]) -> bool {
+fn run_in_vm(_wasm: &Path, _expected: &[String], _should_fail: bool) -> bool {
     true
 }
 
@@ -317,6 +462,11 @@ fn run_pass() {
     TestSuite::new("run-pass").set_run(true).run()
 }
 
+#[test]
+fn run_fail() {
+    TestSuite::new("run-fail").set_should_fail(true).run()
+}
+
 fn for_all_targets<F: FnMut(String)>(sysroot: &str, mut f: F) {
     for target in std::fs::read_dir(format!("{}/lib/rustlib/", sysroot)).unwrap() {
         let target = target.unwrap();
@@ -1,3 +1,5 @@
+// xfail: a leftover miri test, mir2wasm can't compile std programs yet
+
 // error-pattern:no mir for DefId
 
 use std::env;
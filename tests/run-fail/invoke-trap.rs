@@ -0,0 +1,20 @@
+// compile-flags: --invoke fail
+#![feature(intrinsics, lang_items, no_core)]
+#![crate_type = "cdylib"]
+#![no_core]
+
+#[lang="sized"]
+trait Sized {}
+
+#[lang="copy"]
+trait Copy {}
+
+extern "rust-intrinsic" {
+    fn abort() -> !;
+}
+
+// the invocation traps, and mir2wasm exits with an error
+#[no_mangle]
+pub extern fn fail() -> i32 {
+    unsafe { abort() }
+}
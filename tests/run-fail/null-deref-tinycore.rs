@@ -0,0 +1,29 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+#[path = "../run-pass/tinycore/mod.rs"]
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+// debug builds trap at loads and stores in the guard page, instead of reading zeroes
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(1); //~ (i32.const 1)
+    let null = 0 as *const isize;
+    wasm::print_i32(unsafe { *null });
+    0
+}
@@ -0,0 +1,34 @@
+// ignore-interpreter-output
+
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+#[path = "../run-pass/tinycore/mod.rs"]
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+fn add(x: i32, y: i32) -> i32 {
+    x + y
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(add(40, 2) as isize); //~ (i32.const 42)
+    wasm::print_i32(add(2147483647, 1) as isize);
+    0
+}
+//~ panicked at 'attempt to add with overflow', tests/run-fail/panic-report-tinycore.rs:25
@@ -0,0 +1,44 @@
+// compile-flags: --stack-check=trap --stack-size=1024 --stack-placement=end-of-memory
+
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+#[path = "../run-pass/tinycore/mod.rs"]
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+// lives in linear memory, giving each call a 16 bytes frame
+struct Frame {
+    depth: isize,
+    _padding: isize,
+}
+
+fn recurse(depth: isize) -> isize {
+    let frame = Frame { depth: depth, _padding: 0 };
+    if frame.depth < 0 {
+        return 0;
+    }
+    recurse(frame.depth + 1)
+}
+
+// without the check, the stack would overflow into the heap below it, instead of trapping
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(1); //~ (i32.const 1)
+    wasm::print_i32(recurse(0));
+    0
+}
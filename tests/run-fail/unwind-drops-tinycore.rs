@@ -0,0 +1,47 @@
+// compile-flags: --panic=unwind
+// ignore-interpreter-output
+
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+#[path = "../run-pass/tinycore/mod.rs"]
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+struct Guard(isize);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        wasm::print_i32(self.0);
+    }
+}
+
+fn add_one(x: i32) -> i32 {
+    x + 1
+}
+
+// both guards are dropped while unwinding, before the panic traps at the entry point
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    let _a = Guard(1);
+    let _b = Guard(2);
+    wasm::print_i32(add_one(2147483647) as isize);
+    0
+}
+//~ panicked at 'attempt to add with overflow'
+//~ (i32.const 2)
+//~ (i32.const 1)
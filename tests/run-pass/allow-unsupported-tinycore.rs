@@ -0,0 +1,35 @@
+// compile-flags: --allow-unsupported
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+// replaced by a stub trapping when called, the rest of the crate still runs
+pub fn half() -> f64 {
+    0.5
+}
+
+fn double(x: isize) -> isize {
+    x * 2
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(double(21)); //~ (i32.const 42)
+    0
+}
@@ -0,0 +1,76 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+fn double(x: isize) -> isize {
+    x + x
+}
+
+fn apply(f: fn(isize) -> isize, x: isize) -> isize {
+    f(x)
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    let a: i32 = -1;
+    wasm::print_i32(a as u8 as isize); //~ (i32.const 255)
+    wasm::print_i32(a as u16 as i8 as isize); //~ (i32.const -1)
+    wasm::print_i32(a as i64 as isize); //~ (i32.const -1)
+
+    let b: i32 = 300;
+    wasm::print_i32(b as u8 as isize); //~ (i32.const 44)
+    wasm::print_i32(b as i8 as isize); //~ (i32.const 44)
+
+    let c: i64 = 4294967297;
+    wasm::print_i32(c as i32 as isize); //~ (i32.const 1)
+
+    let d = true;
+    wasm::print_i32(d as isize); //~ (i32.const 1)
+
+    // float to int casts saturate at the bounds of the integer type
+    let e: f64 = 300.7;
+    wasm::print_i32(e as u8 as isize); //~ (i32.const 255)
+    wasm::print_i32(e as i32 as isize); //~ (i32.const 300)
+    wasm::print_i32(-1.5f64 as u8 as isize); //~ (i32.const 0)
+    wasm::print_i32(-1e10f64 as i32 as isize); //~ (i32.const -2147483648)
+    wasm::print_i32(2.9f32 as i32 as isize); //~ (i32.const 2)
+    wasm::print_i32(1e20f32 as i64 as i32 as isize); //~ (i32.const -1)
+
+    let f: u32 = 4000000000;
+    wasm::print_i32(f as f64 as i64 as isize); //~ (i32.const -294967296)
+    wasm::print_i32(-3i32 as f32 as i32 as isize); //~ (i32.const -3)
+
+    // pointers round-trip through integers, fat pointers are cast to their data pointer
+    let g: i32 = 42;
+    let ptr = &g as *const i32;
+    wasm::print_i32(unsafe { *(ptr as usize as *const i32) } as isize); //~ (i32.const 42)
+
+    let array: [i32; 3] = [5, 6, 7];
+    let slice: &[i32] = &array;
+    let data = slice as *const [i32] as *const i32;
+    wasm::print_i32(unsafe { *data } as isize); //~ (i32.const 5)
+    let len = (&slice as *const &[i32] as isize + 4) as *const isize;
+    wasm::print_i32(unsafe { *len }); //~ (i32.const 3)
+
+    // fn items reify to fn pointers, called through the function table
+    let h: fn(isize) -> isize = double;
+    wasm::print_i32(h(21)); //~ (i32.const 42)
+    wasm::print_i32(apply(double, 5)); //~ (i32.const 10)
+    0
+}
@@ -0,0 +1,56 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+struct Guard(isize);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        wasm::print_i32(self.0);
+    }
+}
+
+// zero-sized, dropped through a dangling pointer
+struct Unit;
+
+impl Drop for Unit {
+    fn drop(&mut self) {
+        wasm::print_i32(3);
+    }
+}
+
+struct Pair {
+    _first: Guard,
+    _second: Unit,
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    {
+        let _guard = Guard(1);
+        let _unit = Unit;
+    }
+    //~ (i32.const 3)
+    //~ (i32.const 1)
+
+    let _pair = Pair { _first: Guard(4), _second: Unit };
+    0
+}
+//~ (i32.const 4)
+//~ (i32.const 3)
@@ -0,0 +1,28 @@
+// compile-flags: --emit=wast,js,mir
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+// the binary module is still written to the -o path, next to the other artifacts, which the
+// harness checks
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(7); //~ (i32.const 7)
+    0
+}
@@ -0,0 +1,52 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+struct Point {
+    x: isize,
+    y: isize,
+}
+
+fn make_point(x: isize, y: isize) -> Point {
+    Point { x: x, y: y }
+}
+
+// every iteration reuses the same frame slots, so this doesn't grow the stack
+fn sum_points(n: isize) -> isize {
+    let mut total = 0;
+    let mut i = n;
+    loop {
+        match i {
+            0 => break,
+            _ => {}
+        }
+        let p = Point { x: i, y: 1 };
+        let q = make_point(p.y, p.x);
+        total = total + p.x + q.x;
+        i = i + -1;
+    }
+    total
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(sum_points(3)); //~ (i32.const 9)
+    wasm::print_i32(sum_points(10000)); //~ (i32.const 50015000)
+    0
+}
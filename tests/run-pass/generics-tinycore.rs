@@ -0,0 +1,41 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+fn id<T>(t: T) -> T {
+    t
+}
+
+struct Wrapper<T> {
+    value: T,
+}
+
+fn unwrap<T>(wrapper: Wrapper<T>) -> T {
+    wrapper.value
+}
+
+// the instances have the locals and layouts of their type arguments
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(id(42)); //~ (i32.const 42)
+    wasm::print_i32(id(4294967297i64) as i32 as isize); //~ (i32.const 1)
+    wasm::print_i32(id(2.5f64) as isize); //~ (i32.const 2)
+    wasm::print_i32(unwrap(Wrapper { value: 7 })); //~ (i32.const 7)
+    0
+}
@@ -0,0 +1,84 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+extern "rust-intrinsic" {
+    fn size_of<T>() -> usize;
+    fn min_align_of<T>() -> usize;
+    fn ctpop<T>(x: T) -> T;
+    fn ctlz<T>(x: T) -> T;
+    fn cttz<T>(x: T) -> T;
+    fn bswap<T>(x: T) -> T;
+    fn transmute<T, U>(e: T) -> U;
+    fn copy<T>(src: *const T, dst: *mut T, count: usize);
+    fn copy_nonoverlapping<T>(src: *const T, dst: *mut T, count: usize);
+    fn write_bytes<T>(dst: *mut T, val: u8, count: usize);
+    fn offset<T>(dst: *const T, offset: isize) -> *const T;
+    fn sqrtf64(x: f64) -> f64;
+    fn floorf32(x: f32) -> f32;
+    fn volatile_load<T>(src: *const T) -> T;
+    fn volatile_store<T>(dst: *mut T, val: T);
+    fn abort() -> !;
+}
+
+#[repr(C)]
+struct Pair {
+    a: u32,
+    b: u32,
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    unsafe {
+        wasm::print_i32(size_of::<i64>() as isize); //~ (i32.const 8)
+        wasm::print_i32(min_align_of::<i32>() as isize); //~ (i32.const 4)
+        wasm::print_i32(ctpop::<i32>(0xF0) as isize); //~ (i32.const 4)
+        wasm::print_i32(ctlz::<i32>(1) as isize); //~ (i32.const 31)
+        wasm::print_i32(cttz::<i32>(8) as isize); //~ (i32.const 3)
+        wasm::print_i32(bswap::<i32>(0x01020304) as isize); //~ (i32.const 67305985)
+
+        wasm::print_i32(transmute::<f32, u32>(1.0) as isize); //~ (i32.const 1065353216)
+        let pair = transmute::<u64, Pair>(0x0000000200000003);
+        wasm::print_i32(pair.a as isize); //~ (i32.const 3)
+        wasm::print_i32(pair.b as isize); //~ (i32.const 2)
+        wasm::print_i32(transmute::<Pair, u64>(pair) as i32 as isize); //~ (i32.const 3)
+
+        let mut scratch = Pair { a: 0, b: 0 };
+        let a = &mut scratch as *mut Pair as *mut u32;
+        let b = offset(a as *const u32, 1) as *mut u32;
+        write_bytes(a, 1, 2);
+        wasm::print_i32(scratch.b as isize); //~ (i32.const 16843009)
+        volatile_store(a, 42);
+        wasm::print_i32(volatile_load(a as *const u32) as isize); //~ (i32.const 42)
+        copy_nonoverlapping(a as *const u32, b, 1);
+        wasm::print_i32(scratch.b as isize); //~ (i32.const 42)
+        volatile_store(b, 7);
+        copy(b as *const u32, a, 1);
+        wasm::print_i32(scratch.a as isize); //~ (i32.const 7)
+
+        wasm::print_i32(sqrtf64(16.0) as isize); //~ (i32.const 4)
+        wasm::print_i32(floorf32(2.7) as isize); //~ (i32.const 2)
+
+        // trapping is checked by run-fail/abort-tinycore.rs
+        if _i < 0 {
+            abort();
+        }
+    }
+    0
+}
@@ -0,0 +1,42 @@
+// compile-flags: --stack-placement=end-of-memory
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+// linear memory builtins, for allocators
+extern {
+    fn __wasm_current_memory() -> usize;
+    fn __wasm_grow_memory(pages: usize) -> isize;
+    fn __wasm_heap_base() -> usize;
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    unsafe {
+        // the stack ends at the top of the 256 pages of initial memory, and the heap starts there
+        let stack_top = 16777216;
+        let heap_base = __wasm_heap_base() as isize;
+        wasm::print_i32((heap_base >= stack_top) as isize); //~ (i32.const 1)
+
+        // growing the memory adds the pages the heap uses
+        wasm::print_i32(__wasm_grow_memory(1)); //~ (i32.const 256)
+        wasm::print_i32(__wasm_current_memory() as isize); //~ (i32.const 257)
+    }
+    0
+}
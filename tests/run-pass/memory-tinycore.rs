@@ -0,0 +1,40 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+// linear memory builtins, for allocators
+extern {
+    fn __wasm_current_memory() -> usize;
+    fn __wasm_grow_memory(pages: usize) -> isize;
+    fn __wasm_heap_base() -> usize;
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    unsafe {
+        wasm::print_i32(__wasm_current_memory() as isize); //~ (i32.const 256)
+        wasm::print_i32(__wasm_grow_memory(2)); //~ (i32.const 256)
+        wasm::print_i32(__wasm_current_memory() as isize); //~ (i32.const 258)
+
+        // the heap starts after the 64KB stack, itself after the guard page and the statics
+        let heap_base = __wasm_heap_base() as isize;
+        wasm::print_i32((heap_base > 131072) as isize); //~ (i32.const 1)
+    }
+    0
+}
@@ -0,0 +1,54 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+extern "rust-intrinsic" {
+    fn add_with_overflow<T>(x: T, y: T) -> (T, bool);
+    fn sub_with_overflow<T>(x: T, y: T) -> (T, bool);
+    fn mul_with_overflow<T>(x: T, y: T) -> (T, bool);
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    unsafe {
+        let (r, o) = add_with_overflow::<i32>(2147483647, 1);
+        wasm::print_i32(r as isize); //~ (i32.const -2147483648)
+        wasm::print_i32(o as isize); //~ (i32.const 1)
+
+        let (r, o) = add_with_overflow::<i32>(2, 3);
+        wasm::print_i32(r as isize); //~ (i32.const 5)
+        wasm::print_i32(o as isize); //~ (i32.const 0)
+
+        let (r, o) = sub_with_overflow::<u8>(1 as u8, 2 as u8);
+        wasm::print_i32(r as isize); //~ (i32.const 255)
+        wasm::print_i32(o as isize); //~ (i32.const 1)
+
+        let (r, o) = mul_with_overflow::<i8>(16 as i8, 8 as i8);
+        wasm::print_i32(r as isize); //~ (i32.const -128)
+        wasm::print_i32(o as isize); //~ (i32.const 1)
+
+        let (r, o) = mul_with_overflow::<i64>(4294967296, 4294967296);
+        wasm::print_i32(r as isize); //~ (i32.const 0)
+        wasm::print_i32(o as isize); //~ (i32.const 1)
+
+        let (_, o) = add_with_overflow::<i64>(-1, 1);
+        wasm::print_i32(o as isize); //~ (i32.const 0)
+    }
+    0
+}
@@ -10,6 +10,19 @@ pub trait Copy : Clone { }
 
 pub trait Clone : Sized { }
 
+#[lang = "drop"]
+pub trait Drop {
+    fn drop(&mut self);
+}
+
+#[lang = "unsize"]
+pub trait Unsize<T: ?Sized> { }
+
+#[lang = "coerce_unsized"]
+pub trait CoerceUnsized<T> { }
+
+impl<'a, 'b: 'a, T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<&'a U> for &'b T { }
+
 #[lang = "add"]
 pub trait Add<RHS = Self> {
     type Output;
@@ -21,6 +34,58 @@ impl Add for isize {
     fn add(self, rhs: isize) -> Self::Output { self + rhs }
 }
 
+#[lang = "neg"]
+pub trait Neg {
+    type Output;
+    fn neg(self) -> Self::Output;
+}
+
+impl Neg for isize {
+    type Output = isize;
+    fn neg(self) -> Self::Output { -self }
+}
+
+impl Neg for i8 {
+    type Output = i8;
+    fn neg(self) -> Self::Output { -self }
+}
+
+impl Neg for f32 {
+    type Output = f32;
+    fn neg(self) -> Self::Output { -self }
+}
+
+impl Neg for f64 {
+    type Output = f64;
+    fn neg(self) -> Self::Output { -self }
+}
+
+#[lang = "not"]
+pub trait Not {
+    type Output;
+    fn not(self) -> Self::Output;
+}
+
+impl Not for bool {
+    type Output = bool;
+    fn not(self) -> Self::Output { !self }
+}
+
+impl Not for isize {
+    type Output = isize;
+    fn not(self) -> Self::Output { !self }
+}
+
+impl Not for u8 {
+    type Output = u8;
+    fn not(self) -> Self::Output { !self }
+}
+
+impl Not for i64 {
+    type Output = i64;
+    fn not(self) -> Self::Output { !self }
+}
+
 
 #[link(name = "c")]
 extern { }
@@ -0,0 +1,48 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    let a: isize = 5;
+    wasm::print_i32(-a); //~ (i32.const -5)
+    wasm::print_i32(!a); //~ (i32.const -6)
+
+    let b = false;
+    wasm::print_i32(!b as isize); //~ (i32.const 1)
+
+    let c = 15 as u8;
+    wasm::print_i32(!c as isize); //~ (i32.const 240)
+
+    // negating i8::MIN overflows, which debug builds check
+    let d = -100 as i8;
+    wasm::print_i32(-d as isize); //~ (i32.const 100)
+    wasm::print_i32(-(-d) as isize); //~ (i32.const -100)
+
+    let e: i64 = 0;
+    wasm::print_i32(!e as isize); //~ (i32.const -1)
+
+    let f = 2 as f64;
+    wasm::print_i32(-f as isize); //~ (i32.const -2)
+
+    let g = 2.5f32;
+    wasm::print_i32(-g as isize); //~ (i32.const -2)
+    wasm::print_i32(-(-g) as isize); //~ (i32.const 2)
+    0
+}
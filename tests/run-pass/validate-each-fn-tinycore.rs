@@ -0,0 +1,41 @@
+// compile-flags: --validate-each-fn
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+// main is translated before the fns it calls, and ping and pong call each other: none of them is
+// invalid once every fn is translated
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    wasm::print_i32(later()); //~ (i32.const 5)
+    wasm::print_i32(ping(true)); //~ (i32.const 3)
+    0
+}
+
+fn later() -> isize {
+    5
+}
+
+fn ping(again: bool) -> isize {
+    if again { pong(false) + 1 } else { 1 }
+}
+
+fn pong(again: bool) -> isize {
+    ping(again) + 1
+}
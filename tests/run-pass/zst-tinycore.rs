@@ -0,0 +1,57 @@
+#![feature(intrinsics, lang_items, start, no_core, fundamental)]
+#![no_core]
+#![allow(unused_imports)]
+
+pub mod tinycore;
+use tinycore::*;
+
+// access to the wasm "spectest" module test printing functions
+mod wasm {
+    pub fn print_i32(i: isize) {
+        unsafe { _print_i32(i); }
+    }
+
+    #[link(name = "spectest")]
+    extern {
+        #[link_name = "print"]
+        fn _print_i32(i: isize);
+    }
+}
+
+struct Empty;
+
+struct Pair {
+    a: isize,
+    _empty: Empty,
+    b: isize,
+}
+
+fn unit() {}
+
+fn skip_zsts(_u: (), a: isize, _e: Empty, b: isize) -> isize {
+    a + b
+}
+
+fn make_empty() -> Empty {
+    Empty
+}
+
+// unsized referents have a layout of size 0, but aren't zero-sized
+fn first(s: &[isize]) -> isize {
+    let r = &*s;
+    unsafe { *(r as *const [isize] as *const isize) }
+}
+
+#[start]
+fn main(_i: isize, _: *const *const u8) -> isize {
+    let u = unit();
+    let e = make_empty();
+    wasm::print_i32(skip_zsts(u, 3, e, 4)); //~ (i32.const 7)
+
+    let p = Pair { a: 1, _empty: Empty, b: 2 };
+    wasm::print_i32(p.a + p.b); //~ (i32.const 3)
+
+    let array: [isize; 2] = [5, 6];
+    wasm::print_i32(first(&array)); //~ (i32.const 5)
+    0
+}